@@ -73,7 +73,7 @@ fn print_json_items<T: Serialize, I: IntoIterator<Item = T>, W: Write>(mut out:
     }
 }
 
-fn parse_file(code: &str) -> (Vec<FoundType>, Vec<Inherit>, Vec<EntityClass>) {
+fn parse_file(code: &str) -> (Vec<FoundType<'_>>, Vec<Inherit<'_>>, Vec<EntityClass<'_>>) {
     let mut parser = tree_sitter::Parser::new();
     let language = tree_sitter_cpp::LANGUAGE.into();
     parser
@@ -87,16 +87,13 @@ fn parse_file(code: &str) -> (Vec<FoundType>, Vec<Inherit>, Vec<EntityClass>) {
     for f in fn_declarations {
         let matches = find_name_matches(&language, f.body, code);
         for m in matches {
-            for (convert_fn, target_type) in CONVERT_FNS {
-                let convert_code = m.body.utf8_text(code.as_bytes()).unwrap();
-                if convert_code.contains(convert_fn) {
-                    found_types.push(FoundType {
-                        class: f.name,
-                        name: m.name.trim_matches('"'),
-                        ty: target_type,
-                    });
-                    break;
-                }
+            let convert_code = m.body.utf8_text(code.as_bytes()).unwrap();
+            if let Some(convert_fn) = CONVERT_FNS.iter().find(|convert_fn| convert_code.contains(convert_fn.pattern)) {
+                found_types.push(FoundType {
+                    class: f.name,
+                    name: m.name.trim_matches('"'),
+                    ty: convert_fn.ty(convert_code),
+                });
             }
         }
     }
@@ -152,20 +149,12 @@ struct FnDeclaration<'tree, 'code> {
 }
 
 fn find_name_matches<'tree, 'code>(language: &Language, body: Node<'tree>, code: &'code str) -> Vec<NameMatch<'tree, 'code>> {
+    // every if statement in the body, including the ones nested in `else if` chains
     let query = Query::new(
         language,
         r#"(if_statement
-            condition: (_ value: [
-              (binary_expression left: (call_expression
-                  function: (identifier) @cmp_fn
-                  arguments: (argument_list) @cmp_args
-              ))
-              (call_expression
-                  function: (identifier) @cmp_fn
-                  arguments: (argument_list) @cmp_args
-              )
-            ])
-            consequence:(_)  @body
+            condition: (condition_clause value: (_) @condition)
+            consequence: (_) @body
         )"#,
     )
         .expect("invalid query");
@@ -174,31 +163,135 @@ fn find_name_matches<'tree, 'code>(language: &Language, body: Node<'tree>, code:
     let mut iter = cursor.matches(&query, body, code.as_bytes());
     let mut matches = vec![];
     while let Some(decl) = iter.next() {
-        if decl.captures[0].node.utf8_text(code.as_bytes()).unwrap() == "FStrEq" {
-            let args = decl.captures[1].node;
-            if args.named_child(0).unwrap().utf8_text(code.as_bytes()).unwrap() == "szKeyName" {
-                matches.push(NameMatch {
-                    name: args.named_child(1).unwrap().utf8_text(code.as_bytes()).unwrap(),
-                    body: decl.captures[2].node
-                })
-            }
+        if let Some(name) = key_name_comparison(decl.captures[0].node, code) {
+            matches.push(NameMatch {
+                name,
+                body: decl.captures[1].node
+            })
         }
     }
     matches
 }
 
+/// What a string comparison function returns when both strings are equal.
+#[derive(Copy, Clone, PartialEq)]
+enum CompareResult {
+    /// `FStrEq(szKeyName, "name")`
+    True,
+    /// `!Q_stricmp(szKeyName, "name")` or `Q_stricmp(szKeyName, "name") == 0`
+    Zero,
+}
+
+const COMPARE_FNS: &[(&str, CompareResult)] = &[
+    ("FStrEq", CompareResult::True),
+    ("Q_stricmp", CompareResult::Zero),
+    ("V_stricmp", CompareResult::Zero),
+    ("Q_strcmp", CompareResult::Zero),
+    ("V_strcmp", CompareResult::Zero),
+    ("stricmp", CompareResult::Zero),
+    ("strcmp", CompareResult::Zero),
+];
+
+/// Returns the key name if the condition only holds when `szKeyName` equals it.
+fn key_name_comparison<'code>(condition: Node, code: &'code str) -> Option<&'code str> {
+    match condition.kind() {
+        "parenthesized_expression" => key_name_comparison(condition.named_child(0)?, code),
+        "call_expression" => compared_key_name(condition, CompareResult::True, code),
+        "unary_expression" => {
+            if condition.child_by_field_name("operator")?.utf8_text(code.as_bytes()).ok()? != "!" {
+                return None;
+            }
+            compared_key_name(condition.child_by_field_name("argument")?, CompareResult::Zero, code)
+        }
+        "binary_expression" => {
+            let operator = condition.child_by_field_name("operator")?.utf8_text(code.as_bytes()).ok()?;
+            let right = condition.child_by_field_name("right")?.utf8_text(code.as_bytes()).ok()?;
+            let result = match (operator, right) {
+                ("==", "0") | ("!=", "true") => CompareResult::Zero,
+                ("!=", "0") | ("==", "true") => CompareResult::True,
+                _ => return None,
+            };
+            compared_key_name(condition.child_by_field_name("left")?, result, code)
+        }
+        _ => None,
+    }
+}
+
+/// Returns the key name compared by a call like `FStrEq(szKeyName, "name")`,
+/// if a match of the comparison produces `expected`.
+fn compared_key_name<'code>(call: Node, expected: CompareResult, code: &'code str) -> Option<&'code str> {
+    if call.kind() != "call_expression" {
+        return None;
+    }
+    let function = call.child_by_field_name("function")?.utf8_text(code.as_bytes()).ok()?;
+    let (_, result) = COMPARE_FNS.iter().find(|(compare_fn, _)| *compare_fn == function)?;
+    if *result != expected {
+        return None;
+    }
+    let args = call.child_by_field_name("arguments")?;
+    let first = args.named_child(0)?.utf8_text(code.as_bytes()).ok()?;
+    let second = args.named_child(1)?.utf8_text(code.as_bytes()).ok()?;
+    // the key name may be passed on either side of the comparison
+    let name = match (first, second) {
+        ("szKeyName", name) | (name, "szKeyName") => name,
+        _ => return None,
+    };
+    name.starts_with('"').then_some(name)
+}
+
 struct NameMatch<'tree, 'code> {
     name: &'code str,
     body: Node<'tree>,
 }
 
-const CONVERT_FNS: &[(&str, &str)] = &[
-    ("if (val)", "bool"),
-    ("atoi", "i32"),
-    ("UTIL_StringToColor32", "color"),
-    ("SetAbsAngles", "angles"),
-    ("UTIL_StringToVector", "vector"),
-    ("AllocPooledString", "string"),
+/// A pattern in the body of a key name match and the type it converts the value to.
+///
+/// The first pattern found in the body wins, so more specific conversions go first.
+struct ConvertFn {
+    pattern: &'static str,
+    ty: &'static str,
+    /// For array helpers taking `(array, count, string)`, the element counts `ty` holds for,
+    /// any other count is kept as a string.
+    counts: &'static [usize],
+}
+
+impl ConvertFn {
+    const fn new(pattern: &'static str, ty: &'static str) -> Self {
+        ConvertFn { pattern, ty, counts: &[] }
+    }
+
+    fn ty(&self, convert_code: &str) -> &'static str {
+        if self.counts.is_empty() {
+            return self.ty;
+        }
+        match array_count(convert_code, self.pattern) {
+            Some(count) if self.counts.contains(&count) => self.ty,
+            _ => "string",
+        }
+    }
+}
+
+/// The literal element count passed as the second argument of the call to `function`.
+fn array_count(code: &str, function: &str) -> Option<usize> {
+    let call = &code[code.find(function)? + function.len()..];
+    let arguments = call.trim_start().strip_prefix('(')?;
+    arguments.split(',').nth(1)?.trim().parse().ok()
+}
+
+const CONVERT_FNS: &[ConvertFn] = &[
+    ConvertFn::new("if (val)", "bool"),
+    ConvertFn::new("UTIL_StringToColor32", "color"),
+    ConvertFn::new("V_StringToColor32", "color"),
+    // integer arrays of 3 are colors written as "r g b", `Color` has no alpha so "r g b a" stays a string
+    ConvertFn { pattern: "UTIL_StringToIntArray", ty: "color", counts: &[3] },
+    ConvertFn::new("SetAbsAngles", "angles"),
+    ConvertFn::new("AngleVectors", "angles"),
+    ConvertFn::new("UTIL_StringToVector", "vector"),
+    ConvertFn { pattern: "UTIL_StringToFloatArray", ty: "vector", counts: &[3] },
+    ConvertFn::new("SetLocalOrigin", "vector"),
+    ConvertFn::new("atof", "f32"),
+    ConvertFn::new("atoi", "i32"),
+    ConvertFn::new("AllocPooledString", "string"),
 ];
 
 
@@ -234,21 +327,35 @@ struct Inherit<'code> {
     inherits: Vec<&'code str>,
 }
 
-fn find_data_desc_fields(code: &str) -> Vec<FoundType<>> {
+/// Data description macros which register a keyvalue, all shaped like `MACRO(member, FIELD_TYPE, "name")`.
+const KEYFIELD_MACROS: &[&str] = &[
+    "DEFINE_KEYFIELD(",
+    "DEFINE_KEYFIELD_NOT_SAVED(",
+    "DEFINE_INPUT(",
+    "DEFINE_AUTO_ARRAY_KEYFIELD(",
+];
+
+fn find_data_desc_fields(code: &str) -> Vec<FoundType<'_>> {
     let mut result = Vec::new();
     for (start, _) in code.match_indices("BEGIN_DATADESC(") {
         if let Some(end) = code[start..].find("END_DATADESC") {
             let block = &code[start..start+end];
             let class = block[block.find('(').unwrap()+1..block.find(')').unwrap()].trim();
 
-            for (field_start, _) in block.match_indices("DEFINE_KEYFIELD(") {
-                let body = &block[field_start+1..];
-                let end = body.find(')').unwrap();
-                let body = &body[..end];
-                let mut parts = body.split(',').map(str::trim).skip(1);
-                if let (Some(ty), Some(name)) = (parts.next(), parts.next()) {
-                    let name = name.trim_matches('"');
-                    if let Some(ty) = map_type(ty) {
+            for keyfield_macro in KEYFIELD_MACROS {
+                for (field_start, _) in block.match_indices(keyfield_macro) {
+                    let body = &block[field_start+keyfield_macro.len()..];
+                    let end = body.find(')').unwrap();
+                    let body = &body[..end];
+                    let mut parts = body.split(',').map(str::trim).skip(1);
+                    if let (Some(ty), Some(name)) = (parts.next(), parts.next()) {
+                        let name = name.trim_matches('"');
+                        // character arrays hold strings
+                        let ty = if *keyfield_macro == "DEFINE_AUTO_ARRAY_KEYFIELD(" && ty == "FIELD_CHARACTER" {
+                            "string"
+                        } else {
+                            map_type(ty)
+                        };
                         result.push(FoundType {
                             class,
                             name,
@@ -264,23 +371,38 @@ fn find_data_desc_fields(code: &str) -> Vec<FoundType<>> {
 
 const TYPE_MAP: &[(&str, &str)] = &[
     ("FIELD_FLOAT", "f32"),
+    ("FIELD_TIME", "f32"),
     ("FIELD_STRING", "string"),
     ("FIELD_BOOLEAN", "bool"),
+    ("FIELD_CHARACTER", "i8"),
+    ("FIELD_SHORT", "i16"),
     ("FIELD_INTEGER", "i32"),
+    ("FIELD_TICK", "i32"),
+    ("FIELD_MODELINDEX", "i32"),
+    ("FIELD_MATERIALINDEX", "i32"),
+    ("FIELD_INTEGER64", "i64"),
     ("FIELD_COLOR32", "color"),
     ("FIELD_VECTOR", "vector"),
+    ("FIELD_POSITION_VECTOR", "vector"),
+    ("FIELD_VECTOR2D", "vector2d"),
+    ("FIELD_EHANDLE", "ehandle"),
+    ("FIELD_CLASSPTR", "ehandle"),
+    ("FIELD_EDICT", "ehandle"),
+    ("FIELD_MODELNAME", "model"),
+    ("FIELD_SOUNDNAME", "sound"),
 ];
 
-fn map_type(ty: &str) -> Option<&'static str> {
+/// Field types without a dedicated mapping (custom parsers, matrices, ...) keep the raw string.
+fn map_type(ty: &str) -> &'static str {
     for (source_type, target_type) in TYPE_MAP {
         if *source_type == ty {
-            return Some(target_type);
+            return target_type;
         }
     }
-    None
+    "string"
 }
 
-fn get_entity_classes(code: &str) -> Vec<EntityClass> {
+fn get_entity_classes(code: &str) -> Vec<EntityClass<'_>> {
     let mut result = Vec::new();
     for (start, _) in code.match_indices("LINK_ENTITY_TO_CLASS(") {
         let end = code[start..].find(")").unwrap();
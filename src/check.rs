@@ -0,0 +1,228 @@
+use crate::infer::parses_as;
+use crate::schema::{ClassSchema, KeySchema, Provenance, Schema};
+use crate::EntityPropertyType;
+use std::collections::HashMap;
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum GeneratedError {
+    Syn(syn::Error),
+    /// The file has no `Entity` enum.
+    NoEntityEnum,
+    /// A variant of the `Entity` enum has no struct, or no `#[serde(rename)]` with its classname.
+    Variant(String),
+    /// A field has a type that is never generated.
+    UnknownType {
+        class: String,
+        key: String,
+        ty: String,
+    },
+}
+impl std::fmt::Display for GeneratedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeneratedError::Syn(error) => write!(f, "Syn: {error}"),
+            GeneratedError::NoEntityEnum => write!(f, "No Entity enum"),
+            GeneratedError::Variant(variant) => write!(f, "Unrecognized variant: {variant}"),
+            GeneratedError::UnknownType { class, key, ty } => {
+                write!(f, "Unknown type: class={class} key={key} type={ty}")
+            }
+        }
+    }
+}
+impl std::error::Error for GeneratedError {}
+
+/// The value of a `#[serde(name = "value")]` attribute.
+fn serde_attr(attrs: &[syn::Attribute], name: &str) -> Option<String> {
+    let mut value = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        // other serde attributes are not interesting here
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(name) {
+                if let Ok(lit) = meta.value().and_then(|value| value.parse::<syn::LitStr>()) {
+                    value = Some(lit.value());
+                }
+            } else if meta.input.peek(syn::Token![=]) {
+                meta.value()?.parse::<syn::Expr>()?;
+            }
+            Ok(())
+        });
+    }
+    value
+}
+
+fn has_serde_flag(attrs: &[syn::Attribute], name: &str) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("serde"))
+        .any(|attr| {
+            let mut found = false;
+            let _ = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident(name) {
+                    found = true;
+                } else if meta.input.peek(syn::Token![=]) {
+                    meta.value()?.parse::<syn::Expr>()?;
+                }
+                Ok(())
+            });
+            found
+        })
+}
+
+/// The type wrapped in `Option<...>`, if it is one.
+fn option_inner(ty: &syn::Type) -> Option<&syn::Type> {
+    let syn::Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    let syn::PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+    match arguments.args.first()? {
+        syn::GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    }
+}
+
+fn parse_key(classname: &str, field: &syn::Field) -> Result<KeySchema, GeneratedError> {
+    let ident = field.ident.as_ref().map(syn::ext::IdentExt::unraw);
+    let name = serde_attr(&field.attrs, "rename")
+        .or_else(|| ident.map(|ident| ident.to_string()))
+        .unwrap_or_default();
+    let ty = option_inner(&field.ty).unwrap_or(&field.ty);
+    let unknown_type = || GeneratedError::UnknownType {
+        class: classname.to_owned(),
+        key: name.clone(),
+        ty: quote::ToTokens::to_token_stream(&field.ty).to_string(),
+    };
+    Ok(KeySchema {
+        ty: EntityPropertyType::from_rust_type(ty).ok_or_else(unknown_type)?,
+        optional: has_serde_flag(&field.attrs, "default"),
+        count: 0,
+        type_counts: Default::default(),
+        provenance: Provenance::Inferred,
+        name,
+    })
+}
+
+/// Recover the schema from a file generated by [`crate::EntityCollector::codegen`].
+///
+/// Keys typed as strings come back as [`EntityPropertyType::Str`], and nothing is known about the observations.
+pub fn parse_generated(source: &str) -> Result<Schema, GeneratedError> {
+    let file = syn::parse_file(source).map_err(GeneratedError::Syn)?;
+    let mut structs = HashMap::new();
+    let mut entity_enum = None;
+    for item in &file.items {
+        match item {
+            syn::Item::Struct(item) => {
+                structs.insert(item.ident.to_string(), item);
+            }
+            syn::Item::Enum(item) if item.ident == "Entity" => entity_enum = Some(item),
+            _ => {}
+        }
+    }
+    let entity_enum = entity_enum.ok_or(GeneratedError::NoEntityEnum)?;
+
+    let mut schema = Schema::default();
+    for variant in &entity_enum.variants {
+        let variant_error = || GeneratedError::Variant(variant.ident.to_string());
+        let classname = serde_attr(&variant.attrs, "rename").ok_or_else(variant_error)?;
+        let item = structs
+            .get(&variant.ident.to_string())
+            .ok_or_else(variant_error)?;
+        let keys = item
+            .fields
+            .iter()
+            .map(|field| parse_key(&classname, field))
+            .collect::<Result<_, _>>()?;
+        schema.classes.push(ClassSchema {
+            classname,
+            occurrences: 0,
+            sdk_class: None,
+            keys,
+        });
+    }
+    schema.sort();
+    Ok(schema)
+}
+
+/// Why an entity would not round trip through the generated code.
+pub enum Problem {
+    NoClassname,
+    /// The classname has no variant, deserializing the entity fails.
+    UnknownClass(String),
+    /// A required key is missing, deserializing the entity fails.
+    Missing(String),
+    /// A declared key appears more than once, deserializing the entity fails.
+    Duplicate(String),
+    /// The value doesn't parse as the declared type, deserializing the entity fails.
+    Invalid {
+        key: String,
+        value: String,
+        ty: EntityPropertyType,
+    },
+    /// The key is not declared, the value is silently dropped.
+    Dropped {
+        key: String,
+        value: String,
+    },
+}
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::NoClassname => write!(f, "no classname"),
+            Problem::UnknownClass(class) => write!(f, "unknown class={class}"),
+            Problem::Missing(key) => write!(f, "missing key={key}"),
+            Problem::Duplicate(key) => write!(f, "duplicate key={key}"),
+            Problem::Invalid { key, value, ty } => {
+                write!(f, "invalid key={key} value={value:?} type={ty:?}")
+            }
+            Problem::Dropped { key, value } => write!(f, "dropped key={key} value={value:?}"),
+        }
+    }
+}
+
+/// Compare one entity against the schema of the generated code.
+pub fn check_entity(schema: &Schema, entity: &vbsp::RawEntity) -> Vec<Problem> {
+    let Some(classname) = entity.prop("classname") else {
+        return vec![Problem::NoClassname];
+    };
+    let Some(class) = schema.class(classname) else {
+        return vec![Problem::UnknownClass(classname.to_owned())];
+    };
+
+    let mut problems = Vec::new();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for (name, value) in entity.properties() {
+        // the same keys inference ignores
+        if matches!(name, "classname" | "hammerid") || name.is_empty() {
+            continue;
+        }
+        let Some(key) = class.keys.iter().find(|key| key.name == name) else {
+            problems.push(Problem::Dropped {
+                key: name.to_owned(),
+                value: value.to_owned(),
+            });
+            continue;
+        };
+        *seen.entry(name).or_insert(0) += 1;
+        if !parses_as(key.ty, value) {
+            problems.push(Problem::Invalid {
+                key: name.to_owned(),
+                value: value.to_owned(),
+                ty: key.ty,
+            });
+        }
+    }
+    for key in &class.keys {
+        match seen.get(key.name.as_str()) {
+            None if !key.optional => problems.push(Problem::Missing(key.name.clone())),
+            Some(&count) if 1 < count => problems.push(Problem::Duplicate(key.name.clone())),
+            _ => {}
+        }
+    }
+    problems
+}
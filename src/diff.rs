@@ -0,0 +1,149 @@
+use crate::schema::{ClassSchema, KeySchema, Schema};
+use crate::EntityPropertyType;
+use serde::Serialize;
+
+/// One difference between two schemas.
+#[derive(Debug, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    AddedClass {
+        class: String,
+    },
+    RemovedClass {
+        class: String,
+    },
+    AddedKey {
+        class: String,
+        key: String,
+        ty: EntityPropertyType,
+        optional: bool,
+    },
+    RemovedKey {
+        class: String,
+        key: String,
+    },
+    TypeChanged {
+        class: String,
+        key: String,
+        old: EntityPropertyType,
+        new: EntityPropertyType,
+    },
+    BecameRequired {
+        class: String,
+        key: String,
+    },
+    BecameOptional {
+        class: String,
+        key: String,
+    },
+}
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::AddedClass { class } => write!(f, "+ {class}"),
+            Change::RemovedClass { class } => write!(f, "- {class}"),
+            Change::AddedKey {
+                class,
+                key,
+                ty,
+                optional,
+            } => {
+                let optional = if *optional { " optional" } else { "" };
+                write!(f, "+ {class}.{key}: {ty:?}{optional}")
+            }
+            Change::RemovedKey { class, key } => write!(f, "- {class}.{key}"),
+            Change::TypeChanged {
+                class,
+                key,
+                old,
+                new,
+            } => write!(f, "~ {class}.{key}: {old:?} -> {new:?}"),
+            Change::BecameRequired { class, key } => {
+                write!(f, "~ {class}.{key}: optional -> required")
+            }
+            Change::BecameOptional { class, key } => {
+                write!(f, "~ {class}.{key}: required -> optional")
+            }
+        }
+    }
+}
+
+fn diff_key(class: &str, old: &KeySchema, new: &KeySchema, changes: &mut Vec<Change>) {
+    if old.ty != new.ty {
+        changes.push(Change::TypeChanged {
+            class: class.to_owned(),
+            key: new.name.clone(),
+            old: old.ty,
+            new: new.ty,
+        });
+    }
+    match (old.optional, new.optional) {
+        (true, false) => changes.push(Change::BecameRequired {
+            class: class.to_owned(),
+            key: new.name.clone(),
+        }),
+        (false, true) => changes.push(Change::BecameOptional {
+            class: class.to_owned(),
+            key: new.name.clone(),
+        }),
+        _ => {}
+    }
+}
+
+fn diff_class(old: &ClassSchema, new: &ClassSchema, changes: &mut Vec<Change>) {
+    let class = &new.classname;
+    for old_key in &old.keys {
+        if !new.keys.iter().any(|key| key.name == old_key.name) {
+            changes.push(Change::RemovedKey {
+                class: class.clone(),
+                key: old_key.name.clone(),
+            });
+        }
+    }
+    for new_key in &new.keys {
+        match old.keys.iter().find(|key| key.name == new_key.name) {
+            Some(old_key) => diff_key(class, old_key, new_key, changes),
+            None => changes.push(Change::AddedKey {
+                class: class.clone(),
+                key: new_key.name.clone(),
+                ty: new_key.ty,
+                optional: new_key.optional,
+            }),
+        }
+    }
+}
+
+/// Every change needed to turn `old` into `new`, ordered by classname.
+pub fn diff(old: &Schema, new: &Schema) -> Vec<Change> {
+    let mut classnames: Vec<&str> = old
+        .classes
+        .iter()
+        .chain(&new.classes)
+        .map(|class| class.classname.as_str())
+        .collect();
+    classnames.sort();
+    classnames.dedup();
+
+    let mut changes = Vec::new();
+    for classname in classnames {
+        match (old.class(classname), new.class(classname)) {
+            (Some(old), Some(new)) => diff_class(old, new, &mut changes),
+            (Some(_), None) => changes.push(Change::RemovedClass {
+                class: classname.to_owned(),
+            }),
+            (None, Some(new)) => {
+                changes.push(Change::AddedClass {
+                    class: classname.to_owned(),
+                });
+                changes.extend(new.keys.iter().map(|key| Change::AddedKey {
+                    class: classname.to_owned(),
+                    key: key.name.clone(),
+                    ty: key.ty,
+                    optional: key.optional,
+                }));
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+    changes
+}
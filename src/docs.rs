@@ -0,0 +1,431 @@
+use crate::schema::{ClassSchema, Schema};
+use crate::sdk_data::SdkData;
+use crate::stats::ClassStats;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DocsFormat {
+    Markdown,
+    Html,
+}
+
+impl DocsFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            DocsFormat::Markdown => "md",
+            DocsFormat::Html => "html",
+        }
+    }
+}
+
+/// The maps each class appears in, by file stem.
+#[derive(Default)]
+pub struct MapIndex {
+    classes: HashMap<String, BTreeSet<String>>,
+}
+
+impl MapIndex {
+    pub fn add_entities(&mut self, path: &Path, entities: &vbsp::Entities) {
+        let map = path
+            .file_stem()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        for entity in entities {
+            if let Some(class) = entity.prop("classname") {
+                if !self
+                    .classes
+                    .get(class)
+                    .is_some_and(|maps| maps.contains(&*map))
+                {
+                    self.classes
+                        .entry(class.to_owned())
+                        .or_default()
+                        .insert(map.clone().into_owned());
+                }
+            }
+        }
+    }
+}
+
+enum Inline {
+    Text(String),
+    Code(String),
+    Link { text: String, href: String },
+}
+
+type Line = Vec<Inline>;
+
+fn text(text: impl Into<String>) -> Line {
+    vec![Inline::Text(text.into())]
+}
+
+fn code(code: impl Into<String>) -> Line {
+    vec![Inline::Code(code.into())]
+}
+
+enum Block {
+    Heading(u8, String),
+    Paragraph(Line),
+    List(Vec<Line>),
+    Table {
+        header: Vec<&'static str>,
+        rows: Vec<Vec<Line>>,
+    },
+}
+
+struct Page {
+    title: String,
+    blocks: Vec<Block>,
+}
+
+fn markdown_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '#'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn markdown_line(line: &Line) -> String {
+    line.iter()
+        .map(|inline| match inline {
+            Inline::Text(text) => markdown_text(text),
+            // table cells end at any unescaped pipe, even inside code
+            Inline::Code(code) => format!("`{}`", code.replace('`', "'").replace('|', "\\|")),
+            Inline::Link { text, href } => format!("[{}]({href})", markdown_text(text)),
+        })
+        .collect()
+}
+
+fn write_markdown(out: &mut dyn Write, page: &Page) -> std::io::Result<()> {
+    writeln!(out, "# {}", markdown_text(&page.title))?;
+    for block in &page.blocks {
+        writeln!(out)?;
+        match block {
+            Block::Heading(level, heading) => writeln!(
+                out,
+                "{} {}",
+                "#".repeat(*level as usize),
+                markdown_text(heading)
+            )?,
+            Block::Paragraph(line) => writeln!(out, "{}", markdown_line(line))?,
+            Block::List(items) => {
+                for item in items {
+                    writeln!(out, "- {}", markdown_line(item))?;
+                }
+            }
+            Block::Table { header, rows } => {
+                writeln!(out, "| {} |", header.join(" | "))?;
+                writeln!(out, "|{}", "---|".repeat(header.len()))?;
+                for row in rows {
+                    let cells: Vec<String> = row.iter().map(markdown_line).collect();
+                    writeln!(out, "| {} |", cells.join(" | "))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn html_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_line(line: &Line) -> String {
+    line.iter()
+        .map(|inline| match inline {
+            Inline::Text(text) => html_text(text),
+            Inline::Code(code) => format!("<code>{}</code>", html_text(code)),
+            Inline::Link { text, href } => {
+                format!("<a href=\"{}\">{}</a>", html_text(href), html_text(text))
+            }
+        })
+        .collect()
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; max-width: 60em; margin: auto; } \
+table { border-collapse: collapse; } th, td { border: 1px solid #ccc; padding: 0.2em 0.5em; text-align: left; }";
+
+fn write_html(out: &mut dyn Write, page: &Page) -> std::io::Result<()> {
+    let title = html_text(&page.title);
+    writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+    )?;
+    writeln!(
+        out,
+        "<title>{title}</title>\n<style>{HTML_STYLE}</style>\n</head>\n<body>"
+    )?;
+    writeln!(out, "<h1>{title}</h1>")?;
+    for block in &page.blocks {
+        match block {
+            Block::Heading(level, heading) => {
+                writeln!(out, "<h{level}>{}</h{level}>", html_text(heading))?
+            }
+            Block::Paragraph(line) => writeln!(out, "<p>{}</p>", html_line(line))?,
+            Block::List(items) => {
+                writeln!(out, "<ul>")?;
+                for item in items {
+                    writeln!(out, "<li>{}</li>", html_line(item))?;
+                }
+                writeln!(out, "</ul>")?;
+            }
+            Block::Table { header, rows } => {
+                writeln!(
+                    out,
+                    "<table>\n<tr><th>{}</th></tr>",
+                    header.join("</th><th>")
+                )?;
+                for row in rows {
+                    let cells: Vec<String> = row.iter().map(html_line).collect();
+                    writeln!(out, "<tr><td>{}</td></tr>", cells.join("</td><td>"))?;
+                }
+                writeln!(out, "</table>")?;
+            }
+        }
+    }
+    writeln!(out, "</body>\n</html>")
+}
+
+/// The file names of the class pages, keeping to characters safe in paths and links.
+///
+/// Names stay unique ignoring case and clear of the index page, suffixed with a number when taken.
+fn page_names(schema: &Schema, format: DocsFormat) -> HashMap<&str, String> {
+    let mut taken = HashSet::from(["index".to_owned()]);
+    schema
+        .classes
+        .iter()
+        .map(|class| {
+            let name: String = class
+                .classname
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            let name = (1..)
+                .map(|n| match n {
+                    1 => name.clone(),
+                    n => format!("{name}_{n}"),
+                })
+                .find(|name| taken.insert(name.to_ascii_lowercase()))
+                .expect("some suffix is free");
+            (
+                class.classname.as_str(),
+                format!("{name}.{}", format.extension()),
+            )
+        })
+        .collect()
+}
+
+fn number(number: Option<f64>) -> String {
+    number.map(|number| number.to_string()).unwrap_or_default()
+}
+
+fn class_page(
+    class: &ClassSchema,
+    stats: Option<&ClassStats>,
+    sdk_data: &SdkData,
+    maps: Option<&BTreeSet<String>>,
+    format: DocsFormat,
+) -> Page {
+    let mut blocks = vec![Block::Paragraph(vec![Inline::Link {
+        text: "All classes".to_owned(),
+        href: format!("index.{}", format.extension()),
+    }])];
+
+    blocks.push(Block::Heading(2, "SDK class".to_owned()));
+    match &class.sdk_class {
+        Some(sdk_class) => {
+            let mut chain = Line::new();
+            for (i, class) in sdk_data
+                .inheritance_chain(sdk_class)
+                .into_iter()
+                .enumerate()
+            {
+                if i > 0 {
+                    chain.push(Inline::Text(" → ".to_owned()));
+                }
+                chain.push(Inline::Code(class.to_owned()));
+            }
+            blocks.push(Block::Paragraph(chain));
+        }
+        None => blocks.push(Block::Paragraph(text(
+            "Not linked to a class in the SDK data.",
+        ))),
+    }
+
+    blocks.push(Block::Heading(2, "Occurrences".to_owned()));
+    let map_count = stats.map_or(0, |stats| stats.maps);
+    blocks.push(Block::Paragraph(text(format!(
+        "{} instances in {map_count} maps.",
+        class.occurrences
+    ))));
+
+    blocks.push(Block::Heading(2, "Keys".to_owned()));
+    let rows = class
+        .keys
+        .iter()
+        .map(|key| {
+            let key_stats =
+                stats.and_then(|stats| stats.keys.iter().find(|stats| stats.name == key.name));
+            let presence = key_stats.map_or(String::new(), |stats| {
+                format!("{:.1}%", stats.presence * 100.0)
+            });
+            let mut examples = Line::new();
+            for (i, value) in key_stats
+                .into_iter()
+                .flat_map(|stats| &stats.top)
+                .enumerate()
+            {
+                if i > 0 {
+                    examples.push(Inline::Text(", ".to_owned()));
+                }
+                examples.push(Inline::Code(value.value.clone()));
+                examples.push(Inline::Text(format!(" ×{}", value.count)));
+            }
+            vec![
+                code(key.name.clone()),
+                text(format!("{:?}", key.ty)),
+                text(if key.optional { "no" } else { "yes" }),
+                text(presence),
+                text(key_stats.map_or(String::new(), |stats| stats.distinct.to_string())),
+                text(number(key_stats.and_then(|stats| stats.min))),
+                text(number(key_stats.and_then(|stats| stats.max))),
+                examples,
+            ]
+        })
+        .collect();
+    blocks.push(Block::Table {
+        header: vec![
+            "Key", "Type", "Required", "Present", "Distinct", "Min", "Max", "Examples",
+        ],
+        rows,
+    });
+
+    blocks.push(Block::Heading(2, "Maps".to_owned()));
+    blocks.push(Block::List(
+        maps.into_iter()
+            .flatten()
+            .map(|map| code(map.clone()))
+            .collect(),
+    ));
+
+    Page {
+        title: class.classname.clone(),
+        blocks,
+    }
+}
+
+fn index_page(schema: &Schema, stats: &[ClassStats], pages: &HashMap<&str, String>) -> Page {
+    let rows = schema
+        .classes
+        .iter()
+        .map(|class| {
+            let maps = stats
+                .iter()
+                .find(|stats| stats.classname == class.classname)
+                .map_or(0, |stats| stats.maps);
+            vec![
+                vec![Inline::Link {
+                    text: class.classname.clone(),
+                    href: pages[class.classname.as_str()].clone(),
+                }],
+                class.sdk_class.clone().map(code).unwrap_or_default(),
+                text(class.occurrences.to_string()),
+                text(maps.to_string()),
+                text(class.keys.len().to_string()),
+            ]
+        })
+        .collect();
+    Page {
+        title: "Entity classes".to_owned(),
+        blocks: vec![Block::Table {
+            header: vec!["Class", "SDK class", "Instances", "Maps", "Keys"],
+            rows,
+        }],
+    }
+}
+
+/// Write an index and a page per class into `dir`.
+pub fn write_docs(
+    dir: &Path,
+    format: DocsFormat,
+    schema: &Schema,
+    stats: &[ClassStats],
+    sdk_data: &SdkData,
+    maps: &MapIndex,
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let write = |name: &str, page: &Page| {
+        let mut out = std::io::BufWriter::new(std::fs::File::create(dir.join(name))?);
+        match format {
+            DocsFormat::Markdown => write_markdown(&mut out, page)?,
+            DocsFormat::Html => write_html(&mut out, page)?,
+        }
+        out.flush()
+    };
+    let pages = page_names(schema, format);
+    write(
+        &format!("index.{}", format.extension()),
+        &index_page(schema, stats, &pages),
+    )?;
+    for class in &schema.classes {
+        let page = class_page(
+            class,
+            stats
+                .iter()
+                .find(|stats| stats.classname == class.classname),
+            sdk_data,
+            maps.classes.get(&class.classname),
+            format,
+        );
+        write(&pages[class.classname.as_str()], &page)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_names_are_unique_and_leave_the_index_alone() {
+        let class = |classname: &str| ClassSchema {
+            classname: classname.to_owned(),
+            occurrences: 1,
+            sdk_class: None,
+            keys: Vec::new(),
+        };
+        let schema = Schema {
+            classes: vec![
+                class("index"),
+                class("a/b"),
+                class("a:b"),
+                class("A_B"),
+                class("info_target"),
+            ],
+        };
+        let pages = page_names(&schema, DocsFormat::Markdown);
+        assert_eq!(pages["index"], "index_2.md");
+        assert_eq!(pages["a/b"], "a_b.md");
+        assert_eq!(pages["a:b"], "a_b_2.md");
+        assert_eq!(pages["A_B"], "A_B_3.md");
+        assert_eq!(pages["info_target"], "info_target.md");
+    }
+}
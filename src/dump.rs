@@ -0,0 +1,370 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DumpFormat {
+    /// An array of entities, each an ordered list of `[key, value]` pairs.
+    Json,
+    /// One table per classname, with a column per key.
+    Csv,
+    /// The keyvalue text of the entity lump.
+    Text,
+}
+
+/// The keyvalues of every entity, in order and including duplicate keys.
+fn keyvalues(entities: &vbsp::Entities) -> Vec<Vec<(&str, &str)>> {
+    entities
+        .iter()
+        .map(|entity| entity.properties().collect())
+        .collect()
+}
+
+fn write_text(out: &mut dyn Write, entities: &vbsp::Entities) -> std::io::Result<()> {
+    for entity in keyvalues(entities) {
+        writeln!(out, "{{")?;
+        for (key, value) in entity {
+            writeln!(out, "\"{key}\" \"{value}\"")?;
+        }
+        writeln!(out, "}}")?;
+    }
+    Ok(())
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// The columns of one classname, in the order the keys were first seen.
+///
+/// Columns are only ever appended, so a row written before later columns were seen
+/// is completed by padding it with empty fields.
+#[derive(Default)]
+struct Columns(Vec<String>);
+
+impl Columns {
+    /// The csv row of an entity, and its number of fields.
+    fn row(&mut self, map: Option<&str>, entity: &[(&str, &str)]) -> (String, usize) {
+        // duplicate keys share a cell, one value per line
+        let mut cells: Vec<Option<String>> = vec![None; self.0.len()];
+        for &(key, value) in entity {
+            let column = match self.0.iter().position(|column| column == key) {
+                Some(column) => column,
+                None => {
+                    self.0.push(key.to_owned());
+                    cells.push(None);
+                    self.0.len() - 1
+                }
+            };
+            match &mut cells[column] {
+                Some(cell) => {
+                    cell.push('\n');
+                    cell.push_str(value);
+                }
+                cell => *cell = Some(value.to_owned()),
+            }
+        }
+        let mut fields: Vec<String> = map.map(csv_field).into_iter().collect();
+        fields.extend(
+            cells
+                .iter()
+                .map(|cell| cell.as_deref().map(csv_field).unwrap_or_default()),
+        );
+        (fields.join(","), fields.len())
+    }
+
+    fn header(&self, map_column: bool) -> Vec<String> {
+        let mut header: Vec<String> = self.0.iter().map(|key| csv_field(key)).collect();
+        if map_column {
+            header.insert(0, "map".to_owned());
+        }
+        header
+    }
+}
+
+fn write_row(out: &mut dyn Write, row: &[u8], fields: usize, width: usize) -> std::io::Result<()> {
+    out.write_all(row)?;
+    out.write_all(",".repeat(width - fields).as_bytes())?;
+    writeln!(out)
+}
+
+fn classname<'a>(entity: &[(&str, &'a str)]) -> &'a str {
+    entity
+        .iter()
+        .find(|(key, _)| *key == "classname")
+        .map_or("", |(_, value)| value)
+}
+
+/// The file name of each classname's table, keeping to characters safe in paths.
+///
+/// Names stay unique ignoring case, so no two classnames share a file on any file system.
+#[derive(Default)]
+struct FileNames {
+    names: HashMap<String, String>,
+    taken: HashSet<String>,
+}
+
+impl FileNames {
+    fn get(&mut self, classname: &str) -> String {
+        if let Some(name) = self.names.get(classname) {
+            return name.clone();
+        }
+        let safe: String = classname
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '_' | '-') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let safe = if safe.is_empty() {
+            "_no_classname".to_owned()
+        } else {
+            safe
+        };
+        let name = (1..)
+            .map(|n| match n {
+                1 => safe.clone(),
+                n => format!("{safe}_{n}"),
+            })
+            .find(|name| self.taken.insert(name.to_ascii_lowercase()))
+            .expect("some suffix is free");
+        self.names.insert(classname.to_owned(), name.clone());
+        name
+    }
+}
+
+fn create(path: &Path) -> std::io::Result<std::io::BufWriter<std::fs::File>> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(std::io::BufWriter::new(std::fs::File::create(path)?))
+}
+
+/// The rows of one classname in a single map, with their number of fields.
+#[derive(Default)]
+struct Table {
+    columns: Columns,
+    rows: Vec<(String, usize)>,
+}
+
+/// Write one table per classname of a single map into `dir`.
+fn write_csv(dir: &Path, entities: &vbsp::Entities) -> std::io::Result<()> {
+    let mut tables: BTreeMap<&str, Table> = BTreeMap::new();
+    for entity in keyvalues(entities) {
+        let table = tables.entry(classname(&entity)).or_default();
+        let row = table.columns.row(None, &entity);
+        table.rows.push(row);
+    }
+    let mut names = FileNames::default();
+    for (classname, table) in tables {
+        let mut out = create(&dir.join(format!("{}.csv", names.get(classname))))?;
+        let header = table.columns.header(false);
+        writeln!(out, "{}", header.join(","))?;
+        for (row, fields) in table.rows {
+            write_row(&mut out, row.as_bytes(), fields, header.len())?;
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// A table of every map, its rows spooled to a file until the header is known.
+struct SpooledTable {
+    columns: Columns,
+    /// The file name of the table, without extension.
+    name: String,
+    spool: PathBuf,
+    /// Length and number of fields of each spooled row.
+    rows: Vec<(usize, usize)>,
+}
+
+#[derive(Serialize)]
+struct MapEntities<'a> {
+    map: String,
+    entities: Vec<Vec<(&'a str, &'a str)>>,
+}
+
+/// The file each map is written to, named after the map.
+fn map_file(dir: &Path, format: DumpFormat, path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy();
+    match format {
+        DumpFormat::Json => dir.join(format!("{stem}.json")),
+        DumpFormat::Csv => dir.join(&*stem),
+        DumpFormat::Text => dir.join(format!("{stem}.ent")),
+    }
+}
+
+/// Two inputs that would be written to the same file.
+pub fn map_file_collision(paths: &[PathBuf]) -> Option<(&Path, &Path)> {
+    let mut files: HashMap<PathBuf, &Path> = HashMap::new();
+    for path in paths {
+        // the format doesn't matter, every format names files after the stem
+        if let Some(first) = files.insert(map_file(Path::new(""), DumpFormat::Json, path), path) {
+            return Some((first, path));
+        }
+    }
+    None
+}
+
+/// Writes the maps as they are decoded, keeping only the map being written in memory.
+pub struct Dumper {
+    dir: PathBuf,
+    format: DumpFormat,
+    combined: bool,
+    /// The combined json or text file.
+    out: Option<std::io::BufWriter<std::fs::File>>,
+    maps: usize,
+    /// The combined csv tables.
+    tables: BTreeMap<String, SpooledTable>,
+    names: FileNames,
+}
+
+impl Dumper {
+    /// Write every map into its own file in `dir`, or with `combined` all maps into one file,
+    /// or one table per classname for csv.
+    pub fn new(dir: &Path, format: DumpFormat, combined: bool) -> std::io::Result<Self> {
+        let out = match (combined, format) {
+            (true, DumpFormat::Json) => {
+                let mut out = create(&dir.join("entities.json"))?;
+                write!(out, "[")?;
+                Some(out)
+            }
+            (true, DumpFormat::Text) => Some(create(&dir.join("entities.txt"))?),
+            _ => None,
+        };
+        Ok(Self {
+            dir: dir.to_owned(),
+            format,
+            combined,
+            out,
+            maps: 0,
+            tables: BTreeMap::new(),
+            names: FileNames::default(),
+        })
+    }
+
+    pub fn add(&mut self, path: &Path, entities: &vbsp::Entities) -> std::io::Result<()> {
+        self.maps += 1;
+        if !self.combined {
+            let file = map_file(&self.dir, self.format, path);
+            return match self.format {
+                DumpFormat::Json => {
+                    let mut out = create(&file)?;
+                    serde_json::to_writer_pretty(&mut out, &keyvalues(entities))?;
+                    writeln!(out)?;
+                    out.flush()
+                }
+                DumpFormat::Csv => write_csv(&file, entities),
+                DumpFormat::Text => {
+                    let mut out = create(&file)?;
+                    write_text(&mut out, entities)?;
+                    out.flush()
+                }
+            };
+        }
+        match (self.format, &mut self.out) {
+            (DumpFormat::Json, Some(out)) => {
+                if self.maps > 1 {
+                    write!(out, ",")?;
+                }
+                writeln!(out)?;
+                let map = MapEntities {
+                    map: path.display().to_string(),
+                    entities: keyvalues(entities),
+                };
+                serde_json::to_writer_pretty(&mut *out, &map)?;
+                Ok(())
+            }
+            (DumpFormat::Text, Some(out)) => {
+                writeln!(out, "// {}", path.display())?;
+                write_text(out, entities)
+            }
+            _ => self.spool_csv(path, entities),
+        }
+    }
+
+    fn spool_csv(&mut self, path: &Path, entities: &vbsp::Entities) -> std::io::Result<()> {
+        let map = path.display().to_string();
+        let mut rows: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for entity in keyvalues(entities) {
+            let classname = classname(&entity);
+            let table = self.tables.entry(classname.to_owned()).or_insert_with(|| {
+                let name = self.names.get(classname);
+                SpooledTable {
+                    columns: Columns::default(),
+                    spool: self.dir.join(format!(".{name}.csv.rows")),
+                    name,
+                    rows: Vec::new(),
+                }
+            });
+            let (row, fields) = table.columns.row(Some(&map), &entity);
+            table.rows.push((row.len(), fields));
+            rows.entry(classname).or_default().push(row);
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        for (classname, rows) in rows {
+            let spool = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.tables[classname].spool)?;
+            let mut spool = std::io::BufWriter::new(spool);
+            for row in rows {
+                spool.write_all(row.as_bytes())?;
+            }
+            spool.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Complete the combined files.
+    pub fn finish(self) -> std::io::Result<()> {
+        if let Some(mut out) = self.out {
+            if self.format == DumpFormat::Json {
+                writeln!(out, "\n]")?;
+            }
+            out.flush()?;
+        }
+        for table in self.tables.into_values() {
+            let mut out = create(&self.dir.join(format!("{}.csv", table.name)))?;
+            let header = table.columns.header(true);
+            writeln!(out, "{}", header.join(","))?;
+            let mut spool = std::io::BufReader::new(std::fs::File::open(&table.spool)?);
+            let mut row = Vec::new();
+            for (len, fields) in table.rows {
+                row.resize(len, 0);
+                spool.read_exact(&mut row)?;
+                write_row(&mut out, &row, fields, header.len())?;
+            }
+            out.flush()?;
+            std::fs::remove_file(&table.spool)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_stay_inside_the_dir_and_unique() {
+        let mut names = FileNames::default();
+        assert_eq!(names.get("../../escaped"), "______escaped");
+        assert_eq!(names.get("info_target"), "info_target");
+        assert_eq!(names.get("Info_Target"), "Info_Target_2");
+        assert_eq!(names.get("info.target"), "info_target_3");
+        assert_eq!(names.get("info_target"), "info_target");
+        assert_eq!(names.get(""), "_no_classname");
+    }
+}
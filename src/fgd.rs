@@ -0,0 +1,533 @@
+use crate::infer::{ClassCollector, Observations};
+use crate::schema::{ClassSchema, KeySchema, Schema};
+use crate::EntityPropertyType;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+
+/// One output connection, `target,input,parameter,delay,times` with commas or escape characters.
+struct Connection<'a> {
+    target: &'a str,
+    input: &'a str,
+    parameter: &'a str,
+}
+
+fn connection(value: &str) -> Option<Connection<'_>> {
+    let separator = if value.contains('\x1b') { '\x1b' } else { ',' };
+    let fields: Vec<&str> = value.split(separator).collect();
+    let [target, input, parameter, delay, times] = fields[..] else {
+        return None;
+    };
+    if input.is_empty() || delay.parse::<f32>().is_err() || times.parse::<i32>().is_err() {
+        return None;
+    }
+    Some(Connection {
+        target,
+        input,
+        parameter,
+    })
+}
+
+/// The FGD type of an input parameter, widened over every parameter passed to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Parameter {
+    Void,
+    Integer,
+    Float,
+    String,
+}
+
+impl Parameter {
+    fn of(value: &str) -> Self {
+        if value.is_empty() {
+            Parameter::Void
+        } else if value.parse::<i32>().is_ok() {
+            Parameter::Integer
+        } else if value.parse::<f32>().is_ok() {
+            Parameter::Float
+        } else {
+            Parameter::String
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Parameter::Void => "void",
+            Parameter::Integer => "integer",
+            Parameter::Float => "float",
+            Parameter::String => "string",
+        }
+    }
+}
+
+/// The inputs each class receives, found by resolving the targets of every output within its map.
+#[derive(Default)]
+pub struct InputCollector {
+    classes: HashMap<String, BTreeMap<String, Parameter>>,
+    /// The `(class, input)` pairs each output of a class feeds without overriding the parameter.
+    passed: HashMap<String, BTreeMap<String, BTreeSet<(String, String)>>>,
+}
+
+impl InputCollector {
+    pub fn add_entities(&mut self, entities: &vbsp::Entities) {
+        let mut targets: HashMap<&str, Vec<&str>> = HashMap::new();
+        for entity in entities {
+            if let (Some(name), Some(class)) = (entity.prop("targetname"), entity.prop("classname"))
+            {
+                targets.entry(name).or_default().push(class);
+            }
+        }
+        for entity in entities {
+            let source = entity.prop("classname").unwrap_or_default();
+            for (output, connection) in entity
+                .properties()
+                .filter_map(|(key, value)| Some((key, connection(value)?)))
+            {
+                // `!activator` and friends only resolve at runtime
+                for &class in targets.get(connection.target).into_iter().flatten() {
+                    let parameter = self
+                        .classes
+                        .entry(class.to_owned())
+                        .or_default()
+                        .entry(connection.input.to_owned())
+                        .or_insert(Parameter::Void);
+                    *parameter = (*parameter).max(Parameter::of(connection.parameter));
+                    if connection.parameter.is_empty() {
+                        self.passed
+                            .entry(source.to_owned())
+                            .or_default()
+                            .entry(output.to_owned())
+                            .or_default()
+                            .insert((class.to_owned(), connection.input.to_owned()));
+                    }
+                }
+            }
+        }
+    }
+
+    /// The type an output passes, the widest type of the inputs it feeds its own value.
+    fn output_type(&self, class: &str, output: &str) -> Parameter {
+        self.passed
+            .get(class)
+            .and_then(|outputs| outputs.get(output))
+            .into_iter()
+            .flatten()
+            .filter_map(|(class, input)| self.classes.get(class)?.get(input))
+            .copied()
+            .max()
+            .unwrap_or(Parameter::Void)
+    }
+}
+
+/// Every observed value of the key is an output connection.
+fn is_output(collector: Option<&ClassCollector>, key: &KeySchema) -> bool {
+    collector
+        .and_then(|collector| collector.keys.get(&key.name))
+        .is_some_and(|key| {
+            !key.samples.is_empty() && key.samples.iter().all(|value| connection(value).is_some())
+        })
+}
+
+fn is_integer(ty: EntityPropertyType) -> bool {
+    use EntityPropertyType::*;
+    matches!(ty, Bool | Negated | U8 | I8 | U16 | I16 | U32 | I32 | I64)
+}
+
+fn fgd_type(key: &KeySchema) -> &'static str {
+    use EntityPropertyType::*;
+    match (key.name.as_str(), key.ty) {
+        ("targetname", _) => "target_source",
+        ("origin", Vector) => "origin",
+        ("target" | "parentname", _) => "target_destination",
+        ("model", Str) => "studio",
+        (_, Bool | Negated | U8 | I8 | U16 | I16 | U32 | I32 | I64) => "integer",
+        (_, F32) => "float",
+        (_, Color | LightColor) => "color255",
+        (_, Angles) => "angle",
+        (_, Vector) => "vector",
+        (_, ModelName) => "studio",
+        (_, SoundName) => "sound",
+        (_, EntityHandle) => "target_destination",
+        (_, Vector2D | BrushModel | Str) => "string",
+    }
+}
+
+fn value(ty: EntityPropertyType, value: &str) -> String {
+    if is_integer(ty) && value.parse::<i64>().is_ok() {
+        value.to_owned()
+    } else {
+        format!("\"{}\"", value.replace('"', "'"))
+    }
+}
+
+fn display_name(key: &str) -> String {
+    heck::ToTitleCase::to_title_case(key)
+}
+
+/// The bool states and their labels in the spelling the maps use, `0`/`1` unless only `no`/`yes` were seen.
+fn bool_choices(observed: &[&str]) -> Vec<(String, &'static str)> {
+    let words = !observed.is_empty() && observed.iter().all(|value| matches!(*value, "no" | "yes"));
+    let (no, yes) = if words { ("no", "yes") } else { ("0", "1") };
+    let mut choices: Vec<&str> = observed.to_vec();
+    // both states, even when only one was seen
+    choices.extend([no, yes]);
+    choices.sort();
+    choices.dedup();
+    choices
+        .into_iter()
+        .map(|choice| {
+            let label = if matches!(choice, "0" | "no") {
+                "No"
+            } else {
+                "Yes"
+            };
+            (choice.to_owned(), label)
+        })
+        .collect()
+}
+
+/// The FGD lines of a key, tab indented.
+fn key_text(key: &KeySchema, collector: Option<&ClassCollector>) -> String {
+    let stats = collector
+        .and_then(|collector| collector.keys.get(&key.name))
+        .and_then(|key| key.value_stats.as_ref());
+    let histogram = stats.map(|stats| {
+        let mut histogram: Vec<(&str, usize)> = stats
+            .histogram
+            .iter()
+            .map(|(value, &count)| (value.as_str(), count))
+            .collect();
+        histogram.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        histogram
+    });
+    // names and positions are unique to each entity, other keys default to a repeated value
+    let default = histogram
+        .as_ref()
+        .and_then(|histogram| histogram.first())
+        .filter(|&&(_, count)| count > 1)
+        .filter(|_| {
+            !matches!(
+                fgd_type(key),
+                "target_source" | "target_destination" | "origin"
+            )
+        })
+        .map(|&(default, _)| format!(" : {}", value(key.ty, default)))
+        .unwrap_or_default();
+
+    let mut text = String::new();
+    if key.name == "spawnflags" {
+        let Some(histogram) = histogram else {
+            return "\tspawnflags(flags) = []\n".to_owned();
+        };
+        let flags = histogram
+            .iter()
+            .filter_map(|(value, _)| value.parse::<u32>().ok())
+            .fold(0, |flags, value| flags | value);
+        let common = histogram
+            .first()
+            .and_then(|(value, _)| value.parse::<u32>().ok())
+            .unwrap_or(0);
+        text.push_str("\tspawnflags(flags) =\n\t[\n");
+        for bit in (0..32)
+            .map(|bit| 1u32 << bit)
+            .filter(|bit| flags & bit != 0)
+        {
+            let on = u32::from(common & bit != 0);
+            text.push_str(&format!("\t\t{bit} : \"Flag {bit}\" : {on}\n"));
+        }
+        text.push_str("\t]\n");
+        return text;
+    }
+
+    let choosable =
+        (is_integer(key.ty) || key.ty == EntityPropertyType::Str) && !key.names_entities();
+    let choices: Option<Vec<(String, &str)>> = if key.ty == EntityPropertyType::Bool {
+        let observed: Vec<&str> = histogram
+            .iter()
+            .flatten()
+            .map(|&(value, _)| value)
+            .collect();
+        Some(bool_choices(&observed))
+    } else {
+        stats
+            .filter(|_| choosable)
+            .and_then(|stats| stats.choices())
+            .map(|choices| {
+                choices
+                    .into_iter()
+                    .map(|choice| (choice.to_owned(), choice))
+                    .collect()
+            })
+    };
+    let name = display_name(&key.name);
+    match choices {
+        Some(choices) => {
+            text.push_str(&format!(
+                "\t{}(choices) : \"{name}\"{default} =\n\t[\n",
+                key.name
+            ));
+            for (choice, label) in choices {
+                text.push_str(&format!("\t\t{} : \"{label}\"\n", value(key.ty, &choice)));
+            }
+            text.push_str("\t]\n");
+        }
+        None => text.push_str(&format!(
+            "\t{}({}) : \"{name}\"{default}\n",
+            key.name,
+            fgd_type(key)
+        )),
+    }
+    text
+}
+
+/// A class with its keys rendered, before the keys shared with other classes move to base classes.
+struct FgdClass<'a> {
+    class: &'a ClassSchema,
+    solid: bool,
+    /// Key names and their FGD lines.
+    keys: Vec<(&'a str, String)>,
+    outputs: Vec<&'a KeySchema>,
+}
+
+impl<'a> FgdClass<'a> {
+    fn new(class: &'a ClassSchema, collector: Option<&ClassCollector>) -> Self {
+        let key = |name: &str| class.keys.iter().find(|key| key.name == name);
+        let solid = class.classname == "worldspawn"
+            || key("model").is_some_and(|model| model.ty == EntityPropertyType::BrushModel);
+        let (outputs, keys): (Vec<&KeySchema>, Vec<&KeySchema>) =
+            class.keys.iter().partition(|key| is_output(collector, key));
+        let keys = keys
+            .into_iter()
+            // the brush itself
+            .filter(|key| !(solid && key.name == "model"))
+            .map(|key| (key.name.as_str(), key_text(key, collector)))
+            .collect();
+        FgdClass {
+            class,
+            solid,
+            keys,
+            outputs,
+        }
+    }
+}
+
+/// Keys written the same way in several classes, each moved to a base class named after the key,
+/// like `TargetnameBase`, unless the name is taken.
+///
+/// Returns the base class names by key name and the FGD lines of the key.
+fn base_classes<'a>(classes: &[FgdClass<'a>]) -> BTreeMap<&'a str, (String, String)> {
+    let mut counts: HashMap<(&str, &str), usize> = HashMap::new();
+    for class in classes {
+        for (name, text) in &class.keys {
+            *counts.entry((name, text)).or_insert(0) += 1;
+        }
+    }
+    // the most common way each key is written, when shared
+    let mut shared: BTreeMap<&str, (&str, usize)> = BTreeMap::new();
+    for ((name, text), count) in counts {
+        if count < 2 {
+            continue;
+        }
+        let best = shared.entry(name).or_insert((text, count));
+        if (count, std::cmp::Reverse(text)) > (best.1, std::cmp::Reverse(best.0)) {
+            *best = (text, count);
+        }
+    }
+    // Hammer compares class names ignoring case
+    let mut names: BTreeSet<String> = classes
+        .iter()
+        .map(|class| class.class.classname.to_ascii_lowercase())
+        .collect();
+    shared
+        .into_iter()
+        .filter_map(|(key, (text, _))| {
+            let base = format!("{}Base", heck::ToUpperCamelCase::to_upper_camel_case(key));
+            // keys like `_light` and `light` would share a name
+            names
+                .insert(base.to_ascii_lowercase())
+                .then(|| (key, (base, text.to_owned())))
+        })
+        .collect()
+}
+
+fn write_class(
+    out: &mut dyn Write,
+    class: &FgdClass,
+    bases: &BTreeMap<&str, (String, String)>,
+    inputs: &InputCollector,
+) -> std::io::Result<()> {
+    let (kind, helper) = if class.solid {
+        ("SolidClass", "")
+    } else if class.keys.iter().any(|(name, _)| *name == "model") {
+        ("PointClass", " studio()")
+    } else {
+        ("PointClass", "")
+    };
+    let (inherited, keys): (Vec<_>, Vec<_>) = class
+        .keys
+        .iter()
+        .partition(|(name, text)| bases.get(name).is_some_and(|(_, base)| base == text));
+    let base = if inherited.is_empty() {
+        String::new()
+    } else {
+        let names: Vec<&str> = inherited
+            .iter()
+            .map(|(name, _)| bases[name].0.as_str())
+            .collect();
+        format!(" base({})", names.join(", "))
+    };
+    let description = class
+        .class
+        .sdk_class
+        .as_ref()
+        .map_or(String::new(), |sdk_class| {
+            format!(" : \"Implemented by {sdk_class}.\"")
+        });
+    writeln!(
+        out,
+        "@{kind}{base}{helper} = {}{description}\n[",
+        class.class.classname
+    )?;
+
+    for (_, text) in &keys {
+        write!(out, "{text}")?;
+    }
+    let class_inputs = inputs
+        .classes
+        .get(&class.class.classname)
+        .into_iter()
+        .flatten();
+    let has_io = class_inputs.clone().next().is_some() || !class.outputs.is_empty();
+    if !keys.is_empty() && has_io {
+        writeln!(out)?;
+    }
+    for (input, parameter) in class_inputs {
+        writeln!(out, "\tinput {input}({}) : \"\"", parameter.name())?;
+    }
+    for output in &class.outputs {
+        let parameter = inputs.output_type(&class.class.classname, &output.name);
+        writeln!(out, "\toutput {}({}) : \"\"", output.name, parameter.name())?;
+    }
+    writeln!(out, "]\n")
+}
+
+/// Write a class definition for every class in the schema, with choices and spawnflags
+/// from observations collected [`Observations::with_value_stats`].
+///
+/// Keys written the same way in several classes become base classes like `TargetnameBase` and `OriginBase`.
+pub fn write_fgd(
+    out: &mut dyn Write,
+    schema: &Schema,
+    observations: &Observations,
+    inputs: &InputCollector,
+) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "// generated by vbsp-entities-codegen from the observed entities\n"
+    )?;
+    let classes: Vec<FgdClass> = schema
+        .classes
+        .iter()
+        .map(|class| FgdClass::new(class, observations.classes.get(&class.classname)))
+        .collect();
+    let bases = base_classes(&classes);
+    for (base, text) in bases.values() {
+        writeln!(out, "@BaseClass = {base}\n[\n{text}]\n")?;
+    }
+    for class in &classes {
+        write_class(out, class, &bases, inputs)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bool_choices_keep_the_observed_spelling() {
+        assert_eq!(
+            bool_choices(&["no"]),
+            [("no".to_owned(), "No"), ("yes".to_owned(), "Yes")]
+        );
+        assert_eq!(
+            bool_choices(&["1"]),
+            [("0".to_owned(), "No"), ("1".to_owned(), "Yes")]
+        );
+        assert_eq!(
+            bool_choices(&["0", "yes"]),
+            [
+                ("0".to_owned(), "No"),
+                ("1".to_owned(), "Yes"),
+                ("yes".to_owned(), "Yes")
+            ]
+        );
+    }
+
+    #[test]
+    fn outputs_pass_the_type_of_their_inputs() {
+        let mut inputs = InputCollector::default();
+        inputs.add_entities(&vbsp::Entities {
+            entities: r#"{
+"classname" "math_counter"
+"targetname" "counter"
+"outvalue" "relay,trigger,,0,-1"
+}
+{
+"classname" "logic_relay"
+"targetname" "relay"
+"ontrigger" "counter,setvalue,5,0,-1"
+}
+{
+"classname" "logic_compare"
+"oncompare" "counter,setvalue,,0,-1"
+"onequal" "relay,trigger,,0,-1"
+}
+"#
+            .to_owned(),
+        });
+        assert_eq!(
+            inputs.output_type("logic_compare", "oncompare"),
+            Parameter::Integer
+        );
+        assert_eq!(
+            inputs.output_type("logic_compare", "onequal"),
+            Parameter::Void
+        );
+        // the parameter is overridden, so the output passes nothing
+        assert_eq!(
+            inputs.output_type("logic_relay", "ontrigger"),
+            Parameter::Void
+        );
+    }
+
+    #[test]
+    fn base_classes_dont_clash_with_classes() {
+        let class = |classname: &str| ClassSchema {
+            classname: classname.to_owned(),
+            occurrences: 1,
+            sdk_class: None,
+            keys: vec![KeySchema {
+                name: "_light".to_owned(),
+                ty: EntityPropertyType::LightColor,
+                optional: false,
+                count: 1,
+                type_counts: Default::default(),
+                provenance: crate::schema::Provenance::Inferred,
+            }],
+        };
+        let schemas = [class("light"), class("light_spot")];
+        let classes: Vec<FgdClass> = schemas
+            .iter()
+            .map(|class| FgdClass::new(class, None))
+            .collect();
+        let bases = base_classes(&classes);
+        assert_eq!(bases["_light"].0, "LightBase");
+
+        let schemas = [class("light"), class("light_spot"), class("lightbase")];
+        let classes: Vec<FgdClass> = schemas
+            .iter()
+            .map(|class| FgdClass::new(class, None))
+            .collect();
+        assert!(base_classes(&classes).is_empty());
+    }
+}
@@ -0,0 +1,430 @@
+use crate::EntityPropertyType;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use vbsp::EntityProp;
+use vbsp::{Angles, Color, LightColor, Negated, Vector};
+
+/// How many observed values parse successfully as each candidate type.
+pub type TypeCounts = BTreeMap<EntityPropertyType, usize>;
+
+/// Brush entities reference an entry of the bsp models lump as `*N`.
+pub fn get_brush_model_ref(value: &str) -> Option<u32> {
+    value.strip_prefix('*')?.parse().ok()
+}
+
+fn get_bool(value: &str) -> Option<bool> {
+    match value {
+        "0" | "no" => Some(false),
+        "1" | "yes" => Some(true),
+        _ => None,
+    }
+}
+
+/// The types a key may be inferred as, in order of preference.
+fn candidate_types(name: &str) -> Vec<EntityPropertyType> {
+    let mut candidates = Vec::with_capacity(EntityPropertyType::VARIANT_COUNT);
+    if !matches!(name, "spawnflags" | "ammo") {
+        candidates.push(EntityPropertyType::Bool);
+        candidates.push(EntityPropertyType::Negated);
+        candidates.push(EntityPropertyType::U8);
+    }
+    if name != "spawnflags" {
+        candidates.push(EntityPropertyType::U16);
+    }
+    candidates.push(EntityPropertyType::U32);
+    candidates.push(EntityPropertyType::I32);
+    candidates.push(EntityPropertyType::F32);
+    if name.contains("color") || name.contains("light") || name.contains("ambient") {
+        candidates.push(EntityPropertyType::Color);
+    }
+    candidates.push(EntityPropertyType::LightColor);
+    if name.contains("angles") || name.ends_with("dir") {
+        candidates.push(EntityPropertyType::Angles);
+    }
+    candidates.push(EntityPropertyType::Vector);
+    if name == "model" {
+        candidates.push(EntityPropertyType::BrushModel);
+    }
+    candidates
+}
+
+pub fn parses_as(ty: EntityPropertyType, value: &str) -> bool {
+    match ty {
+        EntityPropertyType::Bool => get_bool(value).is_some(),
+        EntityPropertyType::Negated => value.parse::<Negated>().is_ok(),
+        EntityPropertyType::U8 => <u8 as EntityProp>::parse(value).is_ok(),
+        EntityPropertyType::I8 => value.parse::<i8>().is_ok(),
+        EntityPropertyType::U16 => <u16 as EntityProp>::parse(value).is_ok(),
+        EntityPropertyType::I16 => value.parse::<i16>().is_ok(),
+        EntityPropertyType::U32 => <u32 as EntityProp>::parse(value).is_ok(),
+        EntityPropertyType::I32 => <i32 as EntityProp>::parse(value).is_ok(),
+        EntityPropertyType::I64 => value.parse::<i64>().is_ok(),
+        EntityPropertyType::F32 => <f32 as EntityProp>::parse(value).is_ok(),
+        EntityPropertyType::Color => <Color as EntityProp>::parse(value).is_ok(),
+        EntityPropertyType::LightColor => <LightColor as EntityProp>::parse(value).is_ok(),
+        EntityPropertyType::Angles => <Angles as EntityProp>::parse(value).is_ok(),
+        EntityPropertyType::Vector2D => <[f32; 2] as EntityProp>::parse(value).is_ok(),
+        EntityPropertyType::Vector => <Vector as EntityProp>::parse(value).is_ok(),
+        EntityPropertyType::BrushModel => get_brush_model_ref(value).is_some(),
+        EntityPropertyType::ModelName
+        | EntityPropertyType::SoundName
+        | EntityPropertyType::EntityHandle
+        | EntityPropertyType::Str => true,
+    }
+}
+
+/// Choose the first candidate type that parses all `total` values.
+///
+/// When there is none, accept the type parsing the most values if it only has a few outliers.
+/// `samples` are printed to help inspecting the outliers.
+pub fn choose_type(
+    name: &str,
+    counts: &TypeCounts,
+    total: usize,
+    samples: &dyn std::fmt::Debug,
+) -> EntityPropertyType {
+    // counts are sorted by EntityPropertyType, which is the order of preference
+    if let Some((&ty, _)) = counts.iter().find(|&(_, &count)| count == total) {
+        return ty;
+    }
+    let max_count = counts.values().copied().max().unwrap_or(0);
+    if 1 < total && total / 2 < max_count {
+        // why are there outliers that fail to parse?
+        println!("{name}: over 50% parsed, inspect outliers: {counts:?}\n{samples:?}",);
+
+        if total as f32 * 0.99 < max_count as f32 {
+            println!("over 99% parsed, accepting even with outliers");
+            for (&ty, &count) in counts {
+                if count == max_count {
+                    return ty;
+                }
+            }
+        }
+    }
+    EntityPropertyType::Str
+}
+
+/// Distinct values kept per key for inspecting outliers.
+const SAMPLE_LIMIT: usize = 32;
+
+/// Distinct values counted per key for the histogram.
+const HISTOGRAM_LIMIT: usize = 1024;
+
+/// Keys with at most this many distinct values, some of them repeated, have choices.
+const CHOICES_LIMIT: usize = 8;
+
+/// Value statistics of one key, only collected when asked for since they cost memory per distinct value.
+#[derive(Default)]
+pub struct ValueStats {
+    /// Hashes of every distinct value.
+    distinct: HashSet<u64>,
+    /// Occurrences of the first [`HISTOGRAM_LIMIT`] distinct values.
+    pub histogram: HashMap<String, usize>,
+    /// Range of the values parsing as a number.
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl ValueStats {
+    fn observe(&mut self, value: &str) {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        std::hash::Hash::hash(value, &mut hasher);
+        self.distinct.insert(std::hash::Hasher::finish(&hasher));
+        if let Some(count) = self.histogram.get_mut(value) {
+            *count += 1;
+        } else if self.histogram.len() < HISTOGRAM_LIMIT {
+            self.histogram.insert(value.to_owned(), 1);
+        }
+        if let Ok(number) = value.parse::<f64>() {
+            self.min = Some(self.min.map_or(number, |min| min.min(number)));
+            self.max = Some(self.max.map_or(number, |max| max.max(number)));
+        }
+    }
+
+    pub fn distinct(&self) -> usize {
+        self.distinct.len()
+    }
+
+    /// The values, sorted, when the key looks like it picks from a short list.
+    pub fn choices(&self) -> Option<Vec<&str>> {
+        let repeated = self.histogram.values().any(|&count| count > 1);
+        let complete = self.distinct() == self.histogram.len();
+        if !repeated || !complete || !(2..=CHOICES_LIMIT).contains(&self.distinct()) {
+            return None;
+        }
+        let mut choices: Vec<&str> = self.histogram.keys().map(String::as_str).collect();
+        choices.sort();
+        Some(choices)
+    }
+}
+
+/// Observed values of one key, folded one value at a time.
+pub struct KeyCollector {
+    /// Number of observed values.
+    pub count: usize,
+    /// Number of entities with the key, keys repeated within an entity count once.
+    pub entities: usize,
+    pub type_counts: TypeCounts,
+    /// The first [`SAMPLE_LIMIT`] distinct values.
+    pub samples: BTreeSet<String>,
+    pub value_stats: Option<ValueStats>,
+}
+
+impl KeyCollector {
+    fn new(name: &str, value_stats: bool) -> Self {
+        Self {
+            count: 0,
+            entities: 0,
+            type_counts: candidate_types(name)
+                .into_iter()
+                .map(|ty| (ty, 0))
+                .collect(),
+            samples: BTreeSet::new(),
+            value_stats: value_stats.then(ValueStats::default),
+        }
+    }
+
+    fn observe(&mut self, value: &str) {
+        self.count += 1;
+        for (&ty, count) in &mut self.type_counts {
+            if parses_as(ty, value) {
+                *count += 1;
+            }
+        }
+        if self.samples.len() < SAMPLE_LIMIT && !self.samples.contains(value) {
+            self.samples.insert(value.to_owned());
+        }
+        if let Some(value_stats) = &mut self.value_stats {
+            value_stats.observe(value);
+        }
+    }
+
+    /// Infer the type from the observed values, see [`choose_type`].
+    pub fn minimal_type(&self, name: &str) -> EntityPropertyType {
+        choose_type(name, &self.type_counts, self.count, &self.samples)
+    }
+}
+
+pub struct ClassCollector {
+    pub occurrences: usize,
+    /// Number of maps with at least one entity of the class.
+    pub maps: usize,
+    pub keys: HashMap<String, KeyCollector>,
+}
+
+/// Every class observed so far, independent of the maps they were read from.
+#[derive(Default)]
+pub struct Observations {
+    pub classes: HashMap<String, ClassCollector>,
+    /// Also collect [`ValueStats`] for every key.
+    value_stats: bool,
+}
+
+impl Observations {
+    pub fn with_value_stats() -> Self {
+        Self {
+            classes: HashMap::new(),
+            value_stats: true,
+        }
+    }
+
+    /// Fold the entities of one map into the observations.
+    pub fn add_entities(&mut self, entities: &vbsp::Entities) {
+        let mut map_classes = HashSet::new();
+        for ent in entities {
+            let Some(class) = ent.prop("classname") else {
+                println!("No classname in entity! {ent:?}");
+                continue;
+            };
+            if class.is_empty() {
+                println!("empty class ident! class={class}");
+                continue;
+            }
+            if !self.classes.contains_key(class) {
+                let collector = ClassCollector {
+                    occurrences: 0,
+                    maps: 0,
+                    keys: HashMap::new(),
+                };
+                self.classes.insert(class.to_owned(), collector);
+            }
+            let props = self.classes.get_mut(class).unwrap();
+            props.occurrences += 1;
+            if map_classes.insert(class) {
+                props.maps += 1;
+            }
+            let mut entity_keys = HashSet::new();
+            for (name, value) in ent.properties() {
+                if matches!(name, "classname" | "hammerid") {
+                    continue;
+                }
+                if name.is_empty() {
+                    println!("empty ident! class={class} value={value}");
+                    continue;
+                }
+                if !props.keys.contains_key(name) {
+                    let collector = KeyCollector::new(name, self.value_stats);
+                    props.keys.insert(name.to_owned(), collector);
+                }
+                // observed value string
+                let key = props.keys.get_mut(name).unwrap();
+                key.observe(value);
+                if entity_keys.insert(name) {
+                    key.entities += 1;
+                }
+            }
+        }
+    }
+}
+
+impl EntityPropertyType {
+    /// The next wider types in the widening lattice, every value of a type also parses as its parents
+    /// without losing precision.
+    ///
+    /// Floats only hold integers exactly up to 2^24, so only the integer types up to 16 bits widen to
+    /// [`EntityPropertyType::F32`]. Bools may be written `yes`/`no` and angles may have a fourth
+    /// component, so both only widen to [`EntityPropertyType::Str`].
+    fn parents(self) -> &'static [EntityPropertyType] {
+        use EntityPropertyType::*;
+        match self {
+            U8 => &[U16, I16],
+            I8 => &[I16],
+            U16 => &[U32, I32, F32],
+            I16 => &[I32, F32],
+            U32 | I32 => &[I64],
+            Str => &[],
+            _ => &[Str],
+        }
+    }
+
+    /// The type and every type it widens to.
+    fn ancestors(self) -> BTreeSet<EntityPropertyType> {
+        let mut ancestors = BTreeSet::from([self]);
+        let mut queue = vec![self];
+        while let Some(ty) = queue.pop() {
+            for &parent in ty.parents() {
+                if ancestors.insert(parent) {
+                    queue.push(parent);
+                }
+            }
+        }
+        ancestors
+    }
+
+    /// The narrowest type able to hold the values of both types.
+    ///
+    /// A [`EntityPropertyType::Bool`] only known to hold `0` and `1` should be widened as [`EntityPropertyType::U8`].
+    pub fn widen(self, other: EntityPropertyType) -> EntityPropertyType {
+        let other = other.ancestors();
+        // types are ordered from narrow to wide, and Str is an ancestor of every type
+        *self
+            .ancestors()
+            .iter()
+            .find(|ty| other.contains(ty))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPES: [EntityPropertyType; 20] = [
+        EntityPropertyType::Bool,
+        EntityPropertyType::Negated,
+        EntityPropertyType::U8,
+        EntityPropertyType::I8,
+        EntityPropertyType::U16,
+        EntityPropertyType::I16,
+        EntityPropertyType::U32,
+        EntityPropertyType::I32,
+        EntityPropertyType::I64,
+        EntityPropertyType::F32,
+        EntityPropertyType::Color,
+        EntityPropertyType::LightColor,
+        EntityPropertyType::Angles,
+        EntityPropertyType::Vector2D,
+        EntityPropertyType::Vector,
+        EntityPropertyType::BrushModel,
+        EntityPropertyType::ModelName,
+        EntityPropertyType::SoundName,
+        EntityPropertyType::EntityHandle,
+        EntityPropertyType::Str,
+    ];
+
+    const BOUNDARIES: &[&str] = &[
+        "0",
+        "1",
+        "yes",
+        "no",
+        "-1",
+        "127",
+        "128",
+        "-128",
+        "-129",
+        "255",
+        "256",
+        "32767",
+        "32768",
+        "-32769",
+        "65535",
+        "65536",
+        "16777217",
+        "2147483647",
+        "2147483648",
+        "-2147483649",
+        "4294967295",
+        "4294967296",
+        "9223372036854775807",
+        "1.5",
+        "1 2",
+        "1 2 3",
+        "255 255 255 200",
+        "*1",
+        "models/props/crate.mdl",
+        "",
+    ];
+
+    /// The value parses as the type and reads back as the same number.
+    fn holds(ty: EntityPropertyType, value: &str) -> bool {
+        let exact = match (ty, value.parse::<i64>()) {
+            (EntityPropertyType::F32, Ok(integer)) => value
+                .parse::<f32>()
+                .is_ok_and(|float| float as i64 == integer),
+            _ => true,
+        };
+        parses_as(ty, value) && exact
+    }
+
+    #[test]
+    fn widen_holds_values_of_both_types() {
+        for a in TYPES {
+            for b in TYPES {
+                let widened = a.widen(b);
+                assert_eq!(widened, b.widen(a), "{a:?} {b:?}");
+                for value in BOUNDARIES {
+                    for ty in [a, b] {
+                        if holds(ty, value) {
+                            assert!(
+                                holds(widened, value),
+                                "{value:?} is {ty:?} but not {widened:?}, widened from {a:?} and {b:?}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn widen_boundaries() {
+        use EntityPropertyType::*;
+        assert_eq!(U32.widen(I32), I64);
+        assert_eq!(U8.widen(I8), I16);
+        assert_eq!(U16.widen(I16), I32);
+        assert_eq!(Bool.widen(U8), Str);
+        assert_eq!(I64.widen(F32), Str);
+        assert_eq!(U16.widen(F32), F32);
+        assert_eq!(I32.widen(F32), Str);
+        assert_eq!(U8.widen(U8), U8);
+    }
+}
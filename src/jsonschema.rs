@@ -0,0 +1,312 @@
+use crate::infer::{ClassCollector, Observations};
+use crate::schema::{ClassSchema, KeySchema, Schema};
+use crate::EntityPropertyType;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+
+macro_rules! unsigned {
+    () => {
+        r"\+?[0-9]+"
+    };
+}
+macro_rules! signed {
+    () => {
+        r"[+-]?[0-9]+"
+    };
+}
+macro_rules! float {
+    () => {
+        r"([+-]?([0-9]+(\.[0-9]*)?|\.[0-9]+)([eE][+-]?[0-9]+)?|[+-]?([Ii][Nn][Ff]([Ii][Nn][Ii][Tt][Yy])?|[Nn][Aa][Nn]))"
+    };
+}
+
+/// How the values of a type are written in the entity lump, for the JSON Schema and TypeScript output.
+pub struct ValueFormat {
+    /// The name of the shared definition, like the Rust type.
+    pub name: &'static str,
+    /// Matches every value the Rust type parses, integer ranges aside.
+    pub pattern: &'static str,
+    /// The values as a TypeScript type, as close as template literal types get.
+    pub typescript: &'static str,
+}
+
+/// The format of the values of a type, `None` for the string types which take any value.
+pub fn value_format(ty: EntityPropertyType) -> Option<ValueFormat> {
+    let (name, pattern, typescript) = match ty {
+        EntityPropertyType::Bool => ("Bool", "^(0|1|no|yes)$", r#""0" | "1" | "no" | "yes""#),
+        EntityPropertyType::Negated => (
+            "Negated",
+            "^(0|1|allow entities that match criteria)$",
+            r#""0" | "1" | "allow entities that match criteria""#,
+        ),
+        EntityPropertyType::U8 => ("U8", concat!("^", unsigned!(), "$"), "`${number}`"),
+        EntityPropertyType::I8 => ("I8", concat!("^", signed!(), "$"), "`${number}`"),
+        EntityPropertyType::U16 => ("U16", concat!("^", unsigned!(), "$"), "`${number}`"),
+        EntityPropertyType::I16 => ("I16", concat!("^", signed!(), "$"), "`${number}`"),
+        EntityPropertyType::U32 => ("U32", concat!("^", unsigned!(), "$"), "`${number}`"),
+        EntityPropertyType::I32 => ("I32", concat!("^", signed!(), "$"), "`${number}`"),
+        EntityPropertyType::I64 => ("I64", concat!("^", signed!(), "$"), "`${number}`"),
+        EntityPropertyType::F32 => ("F32", concat!("^", float!(), "$"), "`${number}`"),
+        EntityPropertyType::Color => (
+            "Color",
+            concat!(
+                r"^\s*",
+                unsigned!(),
+                r"\s+",
+                unsigned!(),
+                r"\s+",
+                unsigned!(),
+                r"\s*$"
+            ),
+            "`${number} ${number} ${number}`",
+        ),
+        EntityPropertyType::LightColor => (
+            "LightColor",
+            concat!(
+                r"^\s*",
+                unsigned!(),
+                r"\s+",
+                unsigned!(),
+                r"\s+",
+                unsigned!(),
+                r"\s+",
+                unsigned!(),
+                r"\s*$"
+            ),
+            "`${number} ${number} ${number} ${number}`",
+        ),
+        // anything after the angles is ignored
+        EntityPropertyType::Angles => (
+            "Angles",
+            concat!(
+                r"^\s*",
+                float!(),
+                r"\s+",
+                float!(),
+                r"\s+",
+                float!(),
+                r"(\s.*)?$"
+            ),
+            "`${number} ${number} ${number}`",
+        ),
+        EntityPropertyType::Vector2D => (
+            "Vector2D",
+            concat!(r"^\s*", float!(), r"\s+", float!(), r"(\s.*)?$"),
+            "`${number} ${number}`",
+        ),
+        EntityPropertyType::Vector => (
+            "Vector",
+            concat!(
+                r"^\s*",
+                float!(),
+                r"\s+",
+                float!(),
+                r"\s+",
+                float!(),
+                r"\s*$"
+            ),
+            "`${number} ${number} ${number}`",
+        ),
+        EntityPropertyType::BrushModel => (
+            "BrushModelRef",
+            concat!(r"^\*", unsigned!(), "$"),
+            "`*${number}`",
+        ),
+        EntityPropertyType::ModelName
+        | EntityPropertyType::SoundName
+        | EntityPropertyType::EntityHandle
+        | EntityPropertyType::Str => return None,
+    };
+    Some(ValueFormat {
+        name,
+        pattern,
+        typescript,
+    })
+}
+
+/// A string equal to `text` ignoring ascii case, the way the engine compares keys and classnames.
+fn caseless(text: &str) -> Value {
+    let mut pattern = String::from("^");
+    for c in text.chars() {
+        if c.is_ascii_alphabetic() {
+            pattern.extend(['[', c.to_ascii_uppercase(), c.to_ascii_lowercase(), ']']);
+        } else {
+            if r"^$\.*+?()[]{}|/".contains(c) {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+    }
+    pattern.push('$');
+    json!({ "type": "string", "pattern": pattern })
+}
+
+/// A `[key, value]` pair.
+fn pair(key: Value, value: Value) -> Value {
+    json!({ "prefixItems": [key, value] })
+}
+
+fn value_schema(key: &KeySchema, collector: Option<&ClassCollector>) -> Value {
+    let mut schema = match value_format(key.ty) {
+        Some(format) => json!({ "$ref": format!("#/$defs/{}", format.name) }),
+        None => json!({ "type": "string" }),
+    };
+    let choosable = !key.names_entities()
+        && !matches!(
+            key.ty,
+            EntityPropertyType::F32
+                | EntityPropertyType::Color
+                | EntityPropertyType::LightColor
+                | EntityPropertyType::Angles
+                | EntityPropertyType::Vector2D
+                | EntityPropertyType::Vector
+                | EntityPropertyType::BrushModel
+        );
+    let choices = collector
+        .and_then(|collector| collector.keys.get(&key.name))
+        .and_then(|key| key.value_stats.as_ref())
+        .and_then(|stats| stats.choices())
+        .filter(|_| choosable);
+    if let Some(choices) = choices {
+        schema["enum"] = choices.into_iter().map(Value::from).collect();
+    }
+    schema
+}
+
+fn class_schema(class: &ClassSchema, collector: Option<&ClassCollector>) -> Value {
+    let classname = pair(caseless("classname"), caseless(&class.classname));
+    let mut pairs = vec![classname.clone()];
+    let mut required = vec![json!({ "contains": classname })];
+    for key in &class.keys {
+        pairs.push(pair(caseless(&key.name), value_schema(key, collector)));
+        if !key.optional {
+            required.push(json!({ "contains": pair(caseless(&key.name), json!(true)) }));
+        }
+    }
+    // keys that weren't observed can hold anything
+    let known: Vec<Value> = std::iter::once("classname")
+        .chain(class.keys.iter().map(|key| key.name.as_str()))
+        .map(caseless)
+        .collect();
+    pairs.push(pair(json!({ "not": { "anyOf": known } }), json!(true)));
+
+    let mut schema = json!({
+        "type": "array",
+        "items": { "$ref": "#/$defs/KeyValue", "anyOf": pairs },
+        "allOf": required,
+    });
+    if let Some(sdk_class) = &class.sdk_class {
+        schema["description"] = Value::from(format!("Implemented by {sdk_class}."));
+    }
+    schema
+}
+
+/// A JSON Schema for an entity as `dump --format json` writes it, its `[key, value]` string
+/// pairs in lump order, one definition per classname, told apart by the `classname` pair.
+///
+/// Keys and classnames match ignoring case, values match a pattern of what the generated types parse.
+/// Choices are only listed for keys with observations collected [`Observations::with_value_stats`].
+pub fn json_schema(schema: &Schema, observations: &Observations) -> Value {
+    let mut definitions = Map::new();
+    definitions.insert(
+        "KeyValue".to_owned(),
+        json!({
+            "type": "array",
+            "prefixItems": [{ "type": "string" }, { "type": "string" }],
+            "items": false,
+            "minItems": 2,
+        }),
+    );
+    let mut used_types = BTreeSet::new();
+    for class in &schema.classes {
+        definitions.insert(
+            class.classname.clone(),
+            class_schema(class, observations.classes.get(&class.classname)),
+        );
+        used_types.extend(class.keys.iter().map(|key| key.ty));
+    }
+    for format in used_types.into_iter().filter_map(value_format) {
+        definitions.insert(
+            format.name.to_owned(),
+            json!({ "type": "string", "pattern": format.pattern }),
+        );
+    }
+    let variants: Vec<Value> = schema
+        .classes
+        .iter()
+        .map(|class| {
+            // escaped as a JSON pointer
+            let name = class.classname.replace('~', "~0").replace('/', "~1");
+            json!({ "$ref": format!("#/$defs/{name}") })
+        })
+        .collect();
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Entity",
+        "oneOf": variants,
+        "$defs": definitions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infer::parses_as;
+
+    const VALUES: &[&str] = &[
+        "0",
+        "1",
+        "+7",
+        "-1",
+        "25",
+        "1.5",
+        ".5",
+        "2.",
+        "1e3",
+        "-inf",
+        "NaN",
+        "e3",
+        "yes",
+        "no",
+        "allow entities that match criteria",
+        "255 128 0",
+        " 255 128 0 ",
+        "255 128 0 200",
+        "1.5 -2 3e1",
+        "0 90 0 extra",
+        "1 2",
+        "*12",
+        "*",
+        "",
+        "text",
+    ];
+
+    #[test]
+    fn patterns_match_what_the_types_parse() {
+        use EntityPropertyType::*;
+        for ty in [
+            Bool, Negated, U8, I8, U16, I16, U32, I32, I64, F32, Color, LightColor, Angles,
+            Vector2D, Vector, BrushModel,
+        ] {
+            let pattern = regex::Regex::new(value_format(ty).unwrap().pattern).unwrap();
+            for value in VALUES {
+                assert_eq!(
+                    pattern.is_match(value),
+                    parses_as(ty, value),
+                    "{ty:?} {value:?}"
+                );
+            }
+        }
+        assert!(value_format(Str).is_none());
+    }
+
+    #[test]
+    fn keys_match_ignoring_case() {
+        let pattern = caseless("OnTrigger");
+        let pattern = regex::Regex::new(pattern["pattern"].as_str().unwrap()).unwrap();
+        assert!(pattern.is_match("ontrigger"));
+        assert!(pattern.is_match("ONTRIGGER"));
+        assert!(!pattern.is_match("ontrigger2"));
+        assert_eq!(caseless("a.b")["pattern"], r"^[Aa]\.[Bb]$");
+    }
+}
@@ -0,0 +1,15 @@
+//! Reading and replacing the entity lump of bsp files, for scripts patching maps
+//! without going through the command line.
+//!
+//! ```no_run
+//! use vbsp_entities_codegen::patch;
+//!
+//! let bsp = std::fs::read("ctf_2fort.bsp")?;
+//! let entities = "{\n\"classname\" \"worldspawn\"\n}\n";
+//! std::fs::write("fixed.bsp", patch::replace_entity_lump(&bsp, entities)?)?;
+//! std::fs::write("ctf_2fort_l_0.lmp", patch::entity_lmp(&bsp, entities)?)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub mod lump;
+pub mod patch;
@@ -0,0 +1,392 @@
+use std::path::{Path, PathBuf};
+
+/// Index of the entity lump in the lump directory.
+pub const ENTITY_LUMP: i32 = 0;
+
+pub(crate) const LMP_HEADER_SIZE: usize = 20;
+/// The map revision follows the identifier, version and 64 lump entries of 16 bytes.
+const BSP_REVISION_OFFSET: usize = 8 + 64 * 16;
+
+#[derive(Debug)]
+pub enum LumpError {
+    TooShort,
+    NotEntities {
+        lump: i32,
+    },
+    OutOfBounds,
+    /// The compressed entity lump of a Source bsp doesn't decompress.
+    Decompress(lzma_rs::error::Error),
+}
+impl std::fmt::Display for LumpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LumpError::TooShort => write!(f, "file is too short for its header"),
+            LumpError::NotEntities { lump } => {
+                write!(f, "patches lump {lump}, not the entity lump")
+            }
+            LumpError::OutOfBounds => write!(f, "entity lump lies outside of the file"),
+            LumpError::Decompress(error) => write!(f, "entity lump doesn't decompress: {error}"),
+        }
+    }
+}
+impl std::error::Error for LumpError {}
+
+/// The header of a `.lmp` lump patch file.
+#[derive(Debug, Clone, Copy)]
+pub struct LmpHeader {
+    pub offset: i32,
+    pub id: i32,
+    pub version: i32,
+    pub length: i32,
+    pub map_revision: i32,
+}
+
+pub fn read_i32(bytes: &[u8], offset: usize) -> Option<i32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(i32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Split a `.lmp` file into its header and the lump data.
+pub fn read_lmp(bytes: &[u8]) -> Result<(LmpHeader, &[u8]), LumpError> {
+    let field = |index: usize| read_i32(bytes, index * 4).ok_or(LumpError::TooShort);
+    let header = LmpHeader {
+        offset: field(0)?,
+        id: field(1)?,
+        version: field(2)?,
+        length: field(3)?,
+        map_revision: field(4)?,
+    };
+    let data = &bytes[LMP_HEADER_SIZE..];
+    let length = usize::try_from(header.length).unwrap_or(0).min(data.len());
+    Ok((header, &data[..length]))
+}
+
+/// Entity lump text, in the case it was written in unlike [`vbsp`] which lowercases it.
+pub fn entity_text(data: &[u8]) -> String {
+    // the lump is stored with its terminating nul
+    let end = data
+        .iter()
+        .rposition(|&byte| byte != 0)
+        .map_or(0, |last| last + 1);
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+/// The name for a new lump patch of a bsp, loaded after the existing ones.
+pub fn next_patch_file(bsp: &Path) -> PathBuf {
+    let stem = bsp.file_stem().unwrap_or_default().to_string_lossy();
+    let index = patch_files(bsp).len();
+    bsp.with_file_name(format!("{stem}_l_{index}.lmp"))
+}
+
+/// Keyvalue text of the entities, the way compilers write the entity lump.
+pub fn format_entities<E, K, V>(entities: impl IntoIterator<Item = E>) -> String
+where
+    E: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut lump = String::new();
+    for entity in entities {
+        lump.push_str("{\n");
+        for (key, value) in entity {
+            lump.push_str(&format!("\"{}\" \"{}\"\n", key.as_ref(), value.as_ref()));
+        }
+        lump.push_str("}\n");
+    }
+    lump
+}
+
+/// The header and entity text of a `.lmp` patching the entity lump.
+pub fn read_entity_lmp(bytes: &[u8]) -> Result<(LmpHeader, String), LumpError> {
+    let (header, data) = read_lmp(bytes)?;
+    if header.id != ENTITY_LUMP {
+        return Err(LumpError::NotEntities { lump: header.id });
+    }
+    Ok((header, entity_text(data)))
+}
+
+/// The revision of a Source bsp, which lump patches have to match.
+pub fn bsp_revision(bsp: &[u8]) -> Option<i32> {
+    read_i32(bsp, BSP_REVISION_OFFSET)
+}
+
+/// The `<map>_l_<n>.lmp` files next to a bsp, in the order the engine loads them.
+pub fn patch_files(bsp: &Path) -> Vec<PathBuf> {
+    let Some(stem) = bsp.file_stem() else {
+        return Vec::new();
+    };
+    (0..)
+        .map(|index| bsp.with_file_name(format!("{}_l_{index}.lmp", stem.to_string_lossy())))
+        .take_while(|path| path.is_file())
+        .collect()
+}
+
+/// The bsp formats told apart by the magic and version at the start of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BspFormat {
+    /// `VBSP`, read in full by [`vbsp`].
+    Source,
+    /// Version 29 without magic.
+    Quake,
+    /// `BSP2` and `2PSB`, Quake with larger limits.
+    QuakeBsp2,
+    /// Version 30 without magic.
+    GoldSrc,
+    /// `IBSP` version 38.
+    Quake2,
+    /// `IBSP` version 46 and 47.
+    Quake3,
+    /// `RBSP`, Quake 3 with an extra lump for the Raven games.
+    Raven,
+}
+
+impl BspFormat {
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        let magic = bytes.get(..4)?;
+        let version = read_i32(bytes, 4);
+        Some(match magic {
+            b"VBSP" => BspFormat::Source,
+            b"BSP2" | b"2PSB" => BspFormat::QuakeBsp2,
+            b"IBSP" if version == Some(38) => BspFormat::Quake2,
+            b"IBSP" if matches!(version, Some(46 | 47)) => BspFormat::Quake3,
+            b"RBSP" => BspFormat::Raven,
+            _ => match read_i32(bytes, 0)? {
+                29 => BspFormat::Quake,
+                30 => BspFormat::GoldSrc,
+                _ => return None,
+            },
+        })
+    }
+
+    /// Where the lump directory of `(offset, length)` pairs starts.
+    pub fn lump_directory(self) -> usize {
+        match self {
+            BspFormat::Quake | BspFormat::QuakeBsp2 | BspFormat::GoldSrc => 4,
+            BspFormat::Source | BspFormat::Quake2 | BspFormat::Quake3 | BspFormat::Raven => 8,
+        }
+    }
+
+    pub fn lump_count(self) -> usize {
+        match self {
+            BspFormat::Source => 64,
+            BspFormat::Quake | BspFormat::QuakeBsp2 | BspFormat::GoldSrc => 15,
+            BspFormat::Quake2 => 19,
+            BspFormat::Quake3 => 17,
+            BspFormat::Raven => 18,
+        }
+    }
+
+    /// Size of a lump directory entry, Source adds a version and a compression field.
+    pub fn lump_entry_size(self) -> usize {
+        match self {
+            BspFormat::Source => 16,
+            _ => 8,
+        }
+    }
+}
+
+/// The entity text of a bsp without reading anything else, every format keeps its entities
+/// as text in the first lump.
+///
+/// Source maps may compress the lump, it is decompressed like [`vbsp`] does.
+pub fn bsp_entity_text(bytes: &[u8], format: BspFormat) -> Result<String, LumpError> {
+    let directory = format.lump_directory() + ENTITY_LUMP as usize * format.lump_entry_size();
+    let offset = read_i32(bytes, directory).ok_or(LumpError::TooShort)?;
+    let length = read_i32(bytes, directory + 4).ok_or(LumpError::TooShort)?;
+    let start = usize::try_from(offset).map_err(|_| LumpError::OutOfBounds)?;
+    let length = usize::try_from(length).map_err(|_| LumpError::OutOfBounds)?;
+    let data = bytes
+        .get(start..start.saturating_add(length))
+        .ok_or(LumpError::OutOfBounds)?;
+    if format == BspFormat::Source {
+        // the fourCC holds the uncompressed size of compressed lumps
+        let four_cc = read_i32(bytes, directory + 12).ok_or(LumpError::TooShort)?;
+        if four_cc != 0 {
+            return Ok(entity_text(&decompress(data)?));
+        }
+    }
+    Ok(entity_text(data))
+}
+
+/// Decompress a lump behind Valve's header of `LZMA`, the uncompressed and the compressed size.
+fn decompress(data: &[u8]) -> Result<Vec<u8>, LumpError> {
+    let invalid =
+        |message: &str| LumpError::Decompress(lzma_rs::error::Error::LzmaError(message.into()));
+    if data.get(..4) != Some(b"LZMA") {
+        return Err(invalid("missing LZMA header"));
+    }
+    let size = read_i32(data, 4).ok_or(LumpError::TooShort)?;
+    // the properties follow, without the size a .lzma file has
+    let mut input = data.get(12..).ok_or(LumpError::TooShort)?;
+    let mut output = Vec::new();
+    lzma_rs::lzma_decompress_with_options(
+        &mut input,
+        &mut output,
+        &lzma_rs::decompress::Options {
+            unpacked_size: lzma_rs::decompress::UnpackedSize::UseProvided(Some(size as u64)),
+            ..Default::default()
+        },
+    )
+    .map_err(LumpError::Decompress)?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lmp(fields: [i32; 5], data: &[u8]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = fields
+            .iter()
+            .flat_map(|field| field.to_le_bytes())
+            .collect();
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn read_lmp_header() {
+        let bytes = lmp([20, 0, 1, 6, 42], b"{\n}\n\0\0trailing");
+        let (header, data) = read_lmp(&bytes).unwrap();
+        assert_eq!(
+            (
+                header.offset,
+                header.id,
+                header.version,
+                header.length,
+                header.map_revision
+            ),
+            (20, 0, 1, 6, 42)
+        );
+        assert_eq!(data, b"{\n}\n\0\0");
+
+        let (header, text) =
+            read_entity_lmp(&lmp([20, 0, 0, 11, 7], b"{\n\"A\" \"B\"\n}\0")).unwrap();
+        assert_eq!(header.map_revision, 7);
+        assert_eq!(text, "{\n\"A\" \"B\"\n}");
+
+        assert!(matches!(
+            read_entity_lmp(&lmp([20, 3, 0, 0, 0], b"")),
+            Err(LumpError::NotEntities { lump: 3 })
+        ));
+        assert!(matches!(read_lmp(&[0; 19]), Err(LumpError::TooShort)));
+    }
+
+    #[test]
+    fn patch_files_stop_at_the_first_gap() {
+        let dir = std::env::temp_dir().join(format!("lump-patch-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bsp = dir.join("ctf_2fort.bsp");
+        for name in [
+            "ctf_2fort_l_0.lmp",
+            "ctf_2fort_l_1.lmp",
+            "ctf_2fort_l_3.lmp",
+        ] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        let files = patch_files(&bsp);
+        let next = next_patch_file(&bsp);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            files,
+            [dir.join("ctf_2fort_l_0.lmp"), dir.join("ctf_2fort_l_1.lmp")]
+        );
+        assert_eq!(next, dir.join("ctf_2fort_l_2.lmp"));
+    }
+
+    #[test]
+    fn detect_formats() {
+        let header = |magic: &[u8], version: i32| {
+            let mut bytes = magic.to_vec();
+            bytes.extend_from_slice(&version.to_le_bytes());
+            bytes
+        };
+        assert_eq!(
+            BspFormat::detect(&header(b"VBSP", 20)),
+            Some(BspFormat::Source)
+        );
+        assert_eq!(
+            BspFormat::detect(&header(b"IBSP", 38)),
+            Some(BspFormat::Quake2)
+        );
+        assert_eq!(
+            BspFormat::detect(&header(b"IBSP", 46)),
+            Some(BspFormat::Quake3)
+        );
+        assert_eq!(BspFormat::detect(&header(b"IBSP", 30)), None);
+        assert_eq!(
+            BspFormat::detect(&header(b"RBSP", 1)),
+            Some(BspFormat::Raven)
+        );
+        assert_eq!(
+            BspFormat::detect(&header(b"BSP2", 0)),
+            Some(BspFormat::QuakeBsp2)
+        );
+        assert_eq!(
+            BspFormat::detect(&30i32.to_le_bytes()),
+            Some(BspFormat::GoldSrc)
+        );
+        assert_eq!(
+            BspFormat::detect(&29i32.to_le_bytes()),
+            Some(BspFormat::Quake)
+        );
+        assert_eq!(BspFormat::detect(b"VBS"), None);
+    }
+
+    #[test]
+    fn goldsrc_entity_text() {
+        let entities = b"{\n\"classname\" \"Worldspawn\"\n}\n\0";
+        let mut bsp = 30i32.to_le_bytes().to_vec();
+        let offset = 4 + 15 * 8;
+        for lump in 0..15 {
+            let (offset, length) = if lump == 0 {
+                (offset, entities.len())
+            } else {
+                (offset + entities.len(), 0)
+            };
+            bsp.extend_from_slice(&(offset as i32).to_le_bytes());
+            bsp.extend_from_slice(&(length as i32).to_le_bytes());
+        }
+        bsp.extend_from_slice(entities);
+        assert_eq!(
+            bsp_entity_text(&bsp, BspFormat::GoldSrc).unwrap(),
+            "{\n\"classname\" \"Worldspawn\"\n}\n"
+        );
+        assert!(matches!(
+            bsp_entity_text(&bsp[..offset + 4], BspFormat::GoldSrc),
+            Err(LumpError::OutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn source_entity_text_decompresses() {
+        let entities = b"{\n\"classname\" \"Worldspawn\"\n}\n\0";
+        let mut lzma = Vec::new();
+        lzma_rs::lzma_compress(&mut &entities[..], &mut lzma).unwrap();
+        // Valve's header replaces the size of the .lzma header
+        let (properties, stream) = (&lzma[..5], &lzma[13..]);
+        let mut lump = b"LZMA".to_vec();
+        lump.extend_from_slice(&(entities.len() as i32).to_le_bytes());
+        lump.extend_from_slice(&(stream.len() as i32).to_le_bytes());
+        lump.extend_from_slice(properties);
+        lump.extend_from_slice(stream);
+
+        let mut bsp = b"VBSP".to_vec();
+        bsp.extend_from_slice(&20i32.to_le_bytes());
+        for field in [BSP_REVISION_OFFSET + 4, lump.len(), 0, entities.len()] {
+            bsp.extend_from_slice(&(field as i32).to_le_bytes());
+        }
+        bsp.resize(BSP_REVISION_OFFSET + 4, 0);
+        bsp.extend_from_slice(&lump);
+        assert_eq!(
+            bsp_entity_text(&bsp, BspFormat::Source).unwrap(),
+            "{\n\"classname\" \"Worldspawn\"\n}\n"
+        );
+        bsp[BSP_REVISION_OFFSET + 4] = b'X';
+        assert!(matches!(
+            bsp_entity_text(&bsp, BspFormat::Source),
+            Err(LumpError::Decompress(_))
+        ));
+    }
+}
@@ -1,23 +1,79 @@
+mod check;
+mod diff;
+mod docs;
+mod dump;
+mod fgd;
+mod infer;
+mod jsonschema;
+mod normalize;
+mod pipeline;
+mod preserve;
+mod query;
+mod schema;
 mod sdk_data;
+mod stats;
+mod stripper;
+mod typescript;
+mod vmf;
 
 use clap::{Args, Parser, Subcommand};
 use quote::ToTokens;
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
 use std::io::Write;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
-use vbsp::EntityProp;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio};
+use vbsp_entities_codegen::{lump, patch};
 
-use crate::sdk_data::SdkData;
-use vbsp::{Angles, Color, LightColor, Negated, Vector};
+use crate::infer::Observations;
+use crate::schema::{ClassSchema, KeySchema, Provenance, Schema, SchemaError};
+use crate::sdk_data::{SdkData, SdkDataError, SdkDataLayer, UnknownTypePolicy};
 
-fn main() {
+fn main() -> ExitCode {
     let cli = Cli::parse();
     match cli.command {
-        Commands::Generate(command) => {
-            bsp_entities(command.input_files, command.output_file).unwrap()
+        Commands::Generate(command) => report(bsp_entities(command)),
+        Commands::Sdk(command) => report(sdk_entities(
+            command.output_file,
+            command.keep_edits,
+            command.schema_output,
+            command.sdk_data,
+        )),
+        Commands::Emit(command) => report(emit_entities(
+            command.schema,
+            command.output_file,
+            command.keep_edits,
+        )),
+        Commands::Check(command) => report(check_entities(command)),
+        Commands::Normalize(command) => report(normalize_file(command)),
+        Commands::Diff(command) => report(diff_schemas(command)),
+        Commands::Stats(command) => report(corpus_stats(command)),
+        Commands::Query(command) => report(query_entities(command)),
+        Commands::Dump(command) => report(dump_entities(command)),
+        Commands::Patch(command) => report(patch_map(command)),
+        Commands::Docs(command) => report(entity_docs(command)),
+    }
+}
+
+// exit codes distinguishing the kind of failure, clap exits with 2 on usage errors
+const EXIT_OTHER: u8 = 1;
+const EXIT_READ_FAILURE: u8 = 3;
+const EXIT_SCHEMA: u8 = 4;
+const EXIT_FORMAT: u8 = 5;
+const EXIT_CHECK_FAILED: u8 = 6;
+const EXIT_INVALID_PATCH: u8 = 7;
+
+/// An error ending the process, with an exit code describing the kind of failure.
+trait ExitError: std::fmt::Display {
+    fn exit_code(&self) -> u8;
+}
+
+fn report<E: ExitError>(result: Result<(), E>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::from(e.exit_code())
         }
-        Commands::SDK(command) => sdk_entities(command.output_file).unwrap(),
     }
 }
 
@@ -32,7 +88,16 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     Generate(GenerateSubcommand),
-    SDK(SDKSubcommand),
+    Sdk(SdkSubcommand),
+    Emit(EmitSubcommand),
+    Check(CheckSubcommand),
+    Normalize(NormalizeSubcommand),
+    Diff(DiffSubcommand),
+    Stats(StatsSubcommand),
+    Query(QuerySubcommand),
+    Dump(DumpSubcommand),
+    Patch(PatchSubcommand),
+    Docs(DocsSubcommand),
 }
 
 /// Generate entity structs for a specified list of files.
@@ -40,14 +105,264 @@ enum Commands {
 struct GenerateSubcommand {
     #[arg(long, short)]
     output_file: PathBuf,
+    /// Keep the hand-written edits of an existing output file, marked with `#[codegen(keep)]`.
+    #[arg(long)]
+    keep_edits: bool,
+    /// Also write the inferred schema as json.
+    #[arg(long)]
+    schema_output: Option<PathBuf>,
+    /// Also write the classes as a Hammer FGD, with the observed choices, spawnflags and I/O.
+    #[arg(long)]
+    fgd_output: Option<PathBuf>,
+    /// Also write a JSON Schema for the entities as `dump --format json` writes them.
+    #[arg(long)]
+    json_schema_output: Option<PathBuf>,
+    /// Also write TypeScript declarations of the generated types, for the entities `dump --format json --lowercase` writes.
+    #[arg(long)]
+    typescript_output: Option<PathBuf>,
+    /// Merge the observations into a schema saved by a previous run,
+    /// so only new maps need to be read.
+    #[arg(long)]
+    merge_schema: Option<PathBuf>,
     input_files: Vec<PathBuf>,
+    #[command(flatten)]
+    sdk_data: SdkDataArgs,
+    #[command(flatten)]
+    read: ReadArgs,
 }
 
 /// Generate entity structs directly from the source SDK.
 #[derive(Args)]
-struct SDKSubcommand {
+struct SdkSubcommand {
+    #[arg(long, short)]
+    output_file: PathBuf,
+    /// Keep the hand-written edits of an existing output file, marked with `#[codegen(keep)]`.
+    #[arg(long)]
+    keep_edits: bool,
+    /// Also write the schema as json.
+    #[arg(long)]
+    schema_output: Option<PathBuf>,
+    #[command(flatten)]
+    sdk_data: SdkDataArgs,
+}
+
+/// Generate entity structs from a schema written by generate or sdk.
+#[derive(Args)]
+struct EmitSubcommand {
     #[arg(long, short)]
     output_file: PathBuf,
+    /// Keep the hand-written edits of an existing output file, marked with `#[codegen(keep)]`.
+    #[arg(long)]
+    keep_edits: bool,
+    schema: PathBuf,
+}
+
+/// Check that every entity in the maps deserializes with a previously generated file.
+#[derive(Args)]
+struct CheckSubcommand {
+    /// The generated rust file.
+    #[arg(long)]
+    schema: PathBuf,
+    input_files: Vec<PathBuf>,
+    #[command(flatten)]
+    read: ReadArgs,
+}
+
+/// Sort a hand-edited entities file like generate does, keeping attributes and comments.
+#[derive(Args)]
+struct NormalizeSubcommand {
+    file: PathBuf,
+    /// Print the result instead of rewriting the file.
+    #[arg(long)]
+    stdout: bool,
+}
+
+/// List the changes between two generated files, schemas or sets of maps.
+///
+/// A side with a single .rs or .json file is read as generated code or a schema,
+/// anything else as maps.
+#[derive(Args)]
+struct DiffSubcommand {
+    #[arg(long, num_args = 1.., required = true)]
+    old: Vec<PathBuf>,
+    #[arg(long, num_args = 1.., required = true)]
+    new: Vec<PathBuf>,
+    /// Print the changes as json.
+    #[arg(long)]
+    json: bool,
+    /// Write the changes to a file instead of stdout.
+    #[arg(long, short)]
+    output_file: Option<PathBuf>,
+    #[command(flatten)]
+    sdk_data: SdkDataArgs,
+    #[command(flatten)]
+    read: ReadArgs,
+}
+
+/// Report per class and per key statistics of the entities in the maps.
+#[derive(Args)]
+struct StatsSubcommand {
+    /// Print the statistics as json.
+    #[arg(long)]
+    json: bool,
+    /// Number of most common values listed per key.
+    #[arg(long, default_value_t = 5)]
+    top: usize,
+    /// Write the statistics to a file instead of stdout.
+    #[arg(long, short)]
+    output_file: Option<PathBuf>,
+    input_files: Vec<PathBuf>,
+    #[command(flatten)]
+    sdk_data: SdkDataArgs,
+    #[command(flatten)]
+    read: ReadArgs,
+}
+
+/// Print the entities of the maps meeting every filter.
+#[derive(Args)]
+struct QuerySubcommand {
+    /// `NAME=PATTERN` to match the value of a key, `key=PATTERN` to match a key name,
+    /// with `*` and `?` wildcards, like `classname=trigger_*` or `key=filtername`.
+    #[arg(long = "where", short = 'w', value_name = "FILTER")]
+    filters: Vec<query::Filter>,
+    input_files: Vec<PathBuf>,
+    #[command(flatten)]
+    read: ReadArgs,
+}
+
+/// Write the entities of the maps as json, csv or keyvalue text.
+#[derive(Args)]
+struct DumpSubcommand {
+    #[arg(long, value_enum)]
+    format: dump::DumpFormat,
+    /// Directory to write the files to.
+    #[arg(long, short)]
+    output_dir: PathBuf,
+    /// Write all maps into one file instead of one file per map.
+    #[arg(long)]
+    combined: bool,
+    /// Lowercase the entities like every other command reads them, instead of keeping their case.
+    #[arg(long)]
+    lowercase: bool,
+    input_files: Vec<PathBuf>,
+    #[command(flatten)]
+    read: ReadArgs,
+}
+
+/// Replace the entities of a map, or write them as a `.lmp` lump patch.
+#[derive(Args)]
+struct PatchSubcommand {
+    /// The map to patch.
+    bsp: PathBuf,
+    /// The new entities, as keyvalue text or the json written by `dump --format json`.
+    entities: PathBuf,
+    /// Write the patched map, or the lump patch with `--lmp`, to this file.
+    #[arg(long, short, required_unless_present_any = ["in_place", "lmp"])]
+    output_file: Option<PathBuf>,
+    /// Overwrite the map.
+    #[arg(long, conflicts_with_all = ["output_file", "lmp"])]
+    in_place: bool,
+    /// Write a lump patch instead of changing the map, by default next to it as the next `<map>_l_<n>.lmp`.
+    #[arg(long)]
+    lmp: bool,
+}
+
+/// Write reference documentation of the entity classes, a page per classname.
+#[derive(Args)]
+struct DocsSubcommand {
+    #[arg(long, value_enum, default_value_t = docs::DocsFormat::Markdown)]
+    format: docs::DocsFormat,
+    /// Directory to write the pages to.
+    #[arg(long, short)]
+    output_dir: PathBuf,
+    /// Number of example values listed per key.
+    #[arg(long, default_value_t = 5)]
+    examples: usize,
+    input_files: Vec<PathBuf>,
+    #[command(flatten)]
+    sdk_data: SdkDataArgs,
+    #[command(flatten)]
+    read: ReadArgs,
+}
+
+/// Options for reading the input maps.
+#[derive(Args)]
+struct ReadArgs {
+    /// Number of maps decoded in parallel, defaults to the available parallelism.
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<std::num::NonZeroUsize>,
+    /// Directory of Stripper:Source configs, `<map>.cfg` is applied over the entities of `<map>.bsp`.
+    #[arg(long, value_name = "DIR")]
+    stripper: Option<PathBuf>,
+    #[command(flatten)]
+    failure_policy: FailurePolicyArgs,
+    /// Keep the case the entities were written in, instead of lowercasing them like [`vbsp`].
+    #[arg(skip)]
+    preserve_case: bool,
+}
+
+/// What to do when input files fail to read.
+///
+/// Without any of these all inputs are read, but failures still end with a non-zero exit code.
+#[derive(Args)]
+#[group(multiple = false)]
+struct FailurePolicyArgs {
+    /// Stop at the first input that fails to read.
+    #[arg(long)]
+    fail_fast: bool,
+    /// Read every input and tolerate any number of failures, as long as one input was read.
+    #[arg(long)]
+    keep_going: bool,
+    /// Stop once more than N inputs failed, up to N failures are tolerated.
+    #[arg(long, value_name = "N")]
+    max_failures: Option<usize>,
+}
+impl FailurePolicyArgs {
+    /// The number of failures that still allow a successful run,
+    /// None to read every input but fail on any failure.
+    fn tolerated_failures(&self) -> Option<usize> {
+        if self.fail_fast {
+            Some(0)
+        } else if self.keep_going {
+            Some(usize::MAX)
+        } else {
+            self.max_failures
+        }
+    }
+}
+
+/// Options for loading the SDK data.
+#[derive(Args)]
+struct SdkDataArgs {
+    /// Directory with classes.json, inherits.json and types.json from sdk-parser.
+    /// Can be repeated, later directories override earlier ones.
+    #[arg(long = "sdk-data", value_name = "DIR")]
+    sdk_data_dirs: Vec<PathBuf>,
+    /// Do not use the bundled Source SDK 2013 data as the base layer.
+    #[arg(long)]
+    no_bundled_sdk_data: bool,
+    /// Treat unknown SDK types as strings instead of failing.
+    #[arg(long)]
+    lenient_sdk_types: bool,
+}
+impl SdkDataArgs {
+    fn layers(&self) -> Result<Vec<SdkDataLayer>, SdkDataError> {
+        let mut layers = Vec::with_capacity(self.sdk_data_dirs.len() + 1);
+        if !self.no_bundled_sdk_data {
+            layers.push(SdkDataLayer::bundled());
+        }
+        for dir in &self.sdk_data_dirs {
+            layers.push(SdkDataLayer::read_dir(dir)?);
+        }
+        Ok(layers)
+    }
+    fn unknown_type_policy(&self) -> UnknownTypePolicy {
+        if self.lenient_sdk_types {
+            UnknownTypePolicy::Str
+        } else {
+            UnknownTypePolicy::Error
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -55,137 +370,235 @@ struct SDKSubcommand {
 enum ReadBspError {
     Io(std::io::Error),
     Bsp(vbsp::BspError),
+    Vmf(vmf::KeyValuesError),
+    Lump(lump::LumpError),
+    Stripper(stripper::StripperError),
+    Panicked,
 }
 impl std::fmt::Display for ReadBspError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ReadBspError::Io(error) => write!(f, "Io: {}", error),
             ReadBspError::Bsp(bsp_error) => write!(f, "Bsp: {}", bsp_error),
+            ReadBspError::Vmf(vmf_error) => write!(f, "Vmf: {}", vmf_error),
+            ReadBspError::Lump(lump_error) => write!(f, "Lump: {}", lump_error),
+            ReadBspError::Stripper(stripper_error) => write!(f, "Stripper: {}", stripper_error),
+            ReadBspError::Panicked => write!(f, "Panicked while reading"),
         }
     }
 }
 impl std::error::Error for ReadBspError {}
 
-fn read_bsp(path: PathBuf) -> Result<vbsp::Bsp, ReadBspError> {
+/// The entities of a bsp, with the entity lump patches next to a Source bsp applied in load order.
+///
+/// Source maps are read by [`vbsp`], which lowercases the entities, unless the case is preserved.
+fn read_bsp(path: &Path, preserve_case: bool) -> Result<vbsp::Entities, ReadBspError> {
     let entire_file = std::fs::read(path).map_err(ReadBspError::Io)?;
-    let bsp = vbsp::Bsp::read(&entire_file).map_err(ReadBspError::Bsp)?;
-    Ok(bsp)
+    // vbsp only reads Source maps, the older formats only need their entity lump
+    let format = lump::BspFormat::detect(&entire_file);
+    let mut entities = match format {
+        Some(lump::BspFormat::Source) if !preserve_case => {
+            let bsp = vbsp::Bsp::read(&entire_file).map_err(ReadBspError::Bsp)?;
+            // only the entity lump outlives the bsp
+            bsp.entities
+        }
+        Some(format) => vbsp::Entities {
+            entities: lump::bsp_entity_text(&entire_file, format).map_err(ReadBspError::Lump)?,
+        },
+        // vbsp reports what is wrong with the file
+        None => {
+            vbsp::Bsp::read(&entire_file)
+                .map_err(ReadBspError::Bsp)?
+                .entities
+        }
+    };
+    if format != Some(lump::BspFormat::Source) {
+        return Ok(entities);
+    }
+    let revision = lump::bsp_revision(&entire_file);
+    for patch in lump::patch_files(path) {
+        let patch = std::fs::read(patch).map_err(ReadBspError::Io)?;
+        let (header, _) = lump::read_lmp(&patch).map_err(ReadBspError::Lump)?;
+        // the engine ignores patches made for another revision of the map
+        if header.id == lump::ENTITY_LUMP && Some(header.map_revision) == revision {
+            entities.entities = lump::read_entity_lmp(&patch).map_err(ReadBspError::Lump)?.1;
+        }
+    }
+    Ok(entities)
+}
+
+/// Apply `<map>.cfg` from the stripper directory, if there is one for the map.
+fn apply_stripper(
+    path: &Path,
+    stripper: &Path,
+    entities: vbsp::Entities,
+) -> Result<vbsp::Entities, ReadBspError> {
+    let Some(stem) = path.file_stem() else {
+        return Ok(entities);
+    };
+    let config_path = stripper.join(format!("{}.cfg", stem.to_string_lossy()));
+    let source = match std::fs::read(config_path) {
+        Ok(source) => source,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(entities),
+        Err(e) => return Err(ReadBspError::Io(e)),
+    };
+    let config = stripper::Config::parse(&String::from_utf8_lossy(&source))
+        .map_err(ReadBspError::Stripper)?;
+    Ok(vbsp::Entities {
+        entities: config.apply(&entities.entities),
+    })
+}
+
+/// The entities of a compiled .bsp, a hammer .vmf, raw .ent lump text or a .lmp lump patch,
+/// lowercase like [`vbsp`] reads them unless [`ReadArgs::preserve_case`].
+fn read_input_entities(path: &Path, args: &ReadArgs) -> Result<vbsp::Entities, ReadBspError> {
+    let mut entities = read_input_entities_as_written(path, args)?;
+    if !args.preserve_case {
+        entities.entities.make_ascii_lowercase();
+    }
+    Ok(entities)
+}
+
+fn read_input_entities_as_written(
+    path: &Path,
+    args: &ReadArgs,
+) -> Result<vbsp::Entities, ReadBspError> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    let entities = match extension.as_deref() {
+        Some("vmf") => {
+            let source = std::fs::read(path).map_err(ReadBspError::Io)?;
+            let entities =
+                vmf::entity_lump(&String::from_utf8_lossy(&source)).map_err(ReadBspError::Vmf)?;
+            return Ok(vbsp::Entities { entities });
+        }
+        Some("ent") => {
+            let source = std::fs::read(path).map_err(ReadBspError::Io)?;
+            return Ok(vbsp::Entities {
+                entities: lump::entity_text(&source),
+            });
+        }
+        Some("lmp") => {
+            let source = std::fs::read(path).map_err(ReadBspError::Io)?;
+            let (_, entities) = lump::read_entity_lmp(&source).map_err(ReadBspError::Lump)?;
+            return Ok(vbsp::Entities { entities });
+        }
+        _ => read_bsp(path, args.preserve_case)?,
+    };
+    match &args.stripper {
+        Some(stripper) => apply_stripper(path, stripper, entities),
+        None => Ok(entities),
+    }
 }
 
-#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 enum EntityPropertyType {
     Bool,
     Negated,
     U8,
-    // I8,
+    I8,
     U16,
-    // I16,
+    I16,
     U32,
     I32,
+    I64,
     F32,
     Color,
     LightColor,
     Angles,
+    Vector2D,
     Vector,
+    BrushModel,
+    ModelName,
+    SoundName,
+    EntityHandle,
     Str,
 }
 
 impl EntityPropertyType {
-    const VARIANT_COUNT: usize = 12;
+    const VARIANT_COUNT: usize = 20;
+
+    fn rust_type(&self) -> syn::Type {
+        match self {
+            EntityPropertyType::Bool => syn::parse_quote!(bool),
+            EntityPropertyType::Negated => syn::parse_quote!(Negated),
+            EntityPropertyType::U8 => syn::parse_quote!(u8),
+            EntityPropertyType::I8 => syn::parse_quote!(i8),
+            EntityPropertyType::U16 => syn::parse_quote!(u16),
+            EntityPropertyType::I16 => syn::parse_quote!(i16),
+            EntityPropertyType::U32 => syn::parse_quote!(u32),
+            EntityPropertyType::I32 => syn::parse_quote!(i32),
+            EntityPropertyType::I64 => syn::parse_quote!(i64),
+            EntityPropertyType::F32 => syn::parse_quote!(f32),
+            EntityPropertyType::Color => syn::parse_quote!(Color),
+            EntityPropertyType::LightColor => syn::parse_quote!(LightColor),
+            EntityPropertyType::Angles => syn::parse_quote!(Angles),
+            EntityPropertyType::Vector2D => syn::parse_quote!(Vector2D),
+            EntityPropertyType::Vector => syn::parse_quote!(Vector),
+            EntityPropertyType::BrushModel => syn::parse_quote!(BrushModelRef),
+            // model, sound and entity names are kept as the raw string
+            EntityPropertyType::ModelName
+            | EntityPropertyType::SoundName
+            | EntityPropertyType::EntityHandle
+            | EntityPropertyType::Str => syn::parse_quote!(&'a str),
+        }
+    }
+
+    /// The inverse of [`Self::rust_type`], every kind of string comes back as [`EntityPropertyType::Str`].
+    fn from_rust_type(ty: &syn::Type) -> Option<Self> {
+        let path = match ty {
+            syn::Type::Reference(reference) => return matches!(&*reference.elem, syn::Type::Path(path) if path.path.is_ident("str")).then_some(EntityPropertyType::Str),
+            syn::Type::Path(path) => &path.path,
+            _ => return None,
+        };
+        Some(match path.segments.last()?.ident.to_string().as_str() {
+            "bool" => EntityPropertyType::Bool,
+            "Negated" => EntityPropertyType::Negated,
+            "u8" => EntityPropertyType::U8,
+            "i8" => EntityPropertyType::I8,
+            "u16" => EntityPropertyType::U16,
+            "i16" => EntityPropertyType::I16,
+            "u32" => EntityPropertyType::U32,
+            "i32" => EntityPropertyType::I32,
+            "i64" => EntityPropertyType::I64,
+            "f32" => EntityPropertyType::F32,
+            "Color" => EntityPropertyType::Color,
+            "LightColor" => EntityPropertyType::LightColor,
+            "Angles" => EntityPropertyType::Angles,
+            "Vector2D" => EntityPropertyType::Vector2D,
+            "Vector" => EntityPropertyType::Vector,
+            "BrushModelRef" => EntityPropertyType::BrushModel,
+            _ => return None,
+        })
+    }
+
+    /// Whether the generated type borrows from the entity lump.
+    fn has_lifetime(&self) -> bool {
+        matches!(
+            self,
+            EntityPropertyType::ModelName
+                | EntityPropertyType::SoundName
+                | EntityPropertyType::EntityHandle
+                | EntityPropertyType::Str
+        )
+    }
 
     fn codegen(&self, name: &str, optional: bool) -> syn::Field {
+        let rust_type = self.rust_type();
         let (mut attrs, ty) = match self {
             EntityPropertyType::Bool => (
                 vec![syn::parse_quote!(#[serde(deserialize_with = "deserialize_bool")])],
                 // no such thing as Option<bool>
-                syn::parse_quote!(bool),
-            ),
-            EntityPropertyType::Negated => (
-                vec![],
-                if optional {
-                    syn::parse_quote!(Option<Negated>)
-                } else {
-                    syn::parse_quote!(Negated)
-                },
-            ),
-            EntityPropertyType::U8 => (
-                vec![],
-                if optional {
-                    syn::parse_quote!(Option<u8>)
-                } else {
-                    syn::parse_quote!(u8)
-                },
-            ),
-            EntityPropertyType::U16 => (
-                vec![],
-                if optional {
-                    syn::parse_quote!(Option<u16>)
-                } else {
-                    syn::parse_quote!(u16)
-                },
-            ),
-            EntityPropertyType::U32 => (
-                vec![],
-                if optional {
-                    syn::parse_quote!(Option<u32>)
-                } else {
-                    syn::parse_quote!(u32)
-                },
-            ),
-            EntityPropertyType::I32 => (
-                vec![],
-                if optional {
-                    syn::parse_quote!(Option<i32>)
-                } else {
-                    syn::parse_quote!(i32)
-                },
-            ),
-            EntityPropertyType::F32 => (
-                vec![],
-                if optional {
-                    syn::parse_quote!(Option<f32>)
-                } else {
-                    syn::parse_quote!(f32)
-                },
-            ),
-            EntityPropertyType::Color => (
-                vec![],
-                if optional {
-                    syn::parse_quote!(Option<Color>)
-                } else {
-                    syn::parse_quote!(Color)
-                },
+                rust_type,
             ),
-            EntityPropertyType::LightColor => (
+            _ => (
                 vec![],
                 if optional {
-                    syn::parse_quote!(Option<LightColor>)
+                    syn::parse_quote!(Option<#rust_type>)
                 } else {
-                    syn::parse_quote!(LightColor)
-                },
-            ),
-            EntityPropertyType::Angles => (
-                vec![],
-                if optional {
-                    syn::parse_quote!(Option<Angles>)
-                } else {
-                    syn::parse_quote!(Angles)
-                },
-            ),
-            EntityPropertyType::Vector => (
-                vec![],
-                if optional {
-                    syn::parse_quote!(Option<Vector>)
-                } else {
-                    syn::parse_quote!(Vector)
-                },
-            ),
-            EntityPropertyType::Str => (
-                vec![],
-                if optional {
-                    syn::parse_quote!(Option<&'a str>)
-                } else {
-                    syn::parse_quote!(&'a str)
+                    rust_type
                 },
             ),
         };
@@ -215,201 +628,235 @@ impl EntityPropertyType {
             ty,
         }
     }
-}
 
-fn get_bool(value: &str) -> Option<bool> {
-    match value {
-        "0" | "no" => Some(false),
-        "1" | "yes" => Some(true),
-        _ => None,
-    }
-}
-fn get_minimal_type(name: &str, values: &[&str]) -> EntityPropertyType {
-    let mut max_count = 0;
-    // Track how many property values parse successfully for each type.
-    let mut counts = Vec::with_capacity(EntityPropertyType::VARIANT_COUNT);
-    if !matches!(name, "spawnflags" | "ammo") {
-        let count = values.iter().flat_map(|&v| get_bool(v)).count();
-        max_count = max_count.max(count);
-        counts.push((EntityPropertyType::Bool, count));
-        if count == values.len() {
-            return EntityPropertyType::Bool;
-        }
-    }
-    if !matches!(name, "spawnflags" | "ammo") {
-        let count = values.iter().flat_map(|&v| v.parse::<Negated>()).count();
-        max_count = max_count.max(count);
-        counts.push((EntityPropertyType::Negated, count));
-        if count == values.len() {
-            return EntityPropertyType::Negated;
-        }
-    }
-    if !matches!(name, "spawnflags" | "ammo") {
-        let count = values
-            .iter()
-            .flat_map(|&v| <u8 as EntityProp>::parse(v))
-            .count();
-        max_count = max_count.max(count);
-        counts.push((EntityPropertyType::U8, count));
-        if count == values.len() {
-            return EntityPropertyType::U8;
-        }
-    }
-    // if values.iter().all(|&v|<i8 as EntityProp>::parse(v).is_ok()){
-    // 	let count=values.iter().flat_map(|&v|<u8 as EntityProp>::parse(v)).count();
-    // 	max_count=max_count.max(count);
-    // 	if count==values.len(){
-    // 		return EntityPropertyType::U8;
-    // 	}
-    // 	return EntityPropertyType::I8;
-    // }
-    if name != "spawnflags" {
-        let count = values
-            .iter()
-            .flat_map(|&v| <u16 as EntityProp>::parse(v))
-            .count();
-        max_count = max_count.max(count);
-        counts.push((EntityPropertyType::U16, count));
-        if count == values.len() {
-            return EntityPropertyType::U16;
-        }
-    }
-    // if values.iter().all(|&v|<i16 as EntityProp>::parse(v).is_ok()){
-    // 	let count=values.iter().flat_map(|&v|<u8 as EntityProp>::parse(v)).count();
-    // 	max_count=max_count.max(count);
-    // 	if count==values.len(){
-    // 		return EntityPropertyType::U8;
-    // 	}
-    // 	return EntityPropertyType::I16;
-    // }
-    {
-        let count = values
-            .iter()
-            .flat_map(|&v| <u32 as EntityProp>::parse(v))
-            .count();
-        max_count = max_count.max(count);
-        counts.push((EntityPropertyType::U32, count));
-        if count == values.len() {
-            return EntityPropertyType::U32;
-        }
-    }
-    {
-        let count = values
-            .iter()
-            .flat_map(|&v| <i32 as EntityProp>::parse(v))
-            .count();
-        max_count = max_count.max(count);
-        counts.push((EntityPropertyType::I32, count));
-        if count == values.len() {
-            return EntityPropertyType::I32;
+    /// Items the generated file needs to define for this type to be usable.
+    fn support_items(&self) -> Vec<syn::Item> {
+        match self {
+            EntityPropertyType::BrushModel => vec![
+                syn::parse_quote! {
+                    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+                    pub struct BrushModelRef(pub u32);
+                },
+                syn::parse_quote! {
+                    impl BrushModelRef {
+                        pub fn index(&self) -> usize {
+                            self.0 as usize
+                        }
+                        pub fn resolve<'b>(&self, bsp: &'b vbsp::Bsp) -> Option<&'b vbsp::Model> {
+                            bsp.models.get(self.index())
+                        }
+                    }
+                },
+                syn::parse_quote! {
+                    impl<'de> Deserialize<'de> for BrushModelRef {
+                        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                            let raw = <&'de str>::deserialize(deserializer)?;
+                            raw.strip_prefix('*')
+                                .and_then(|index| index.parse().ok())
+                                .map(BrushModelRef)
+                                .ok_or_else(|| {
+                                    serde::de::Error::invalid_value(
+                                        serde::de::Unexpected::Str(raw),
+                                        &"a brush model reference like *12",
+                                    )
+                                })
+                        }
+                    }
+                },
+            ],
+            EntityPropertyType::Vector2D => vec![
+                syn::parse_quote! {
+                    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+                    pub struct Vector2D {
+                        pub x: f32,
+                        pub y: f32,
+                    }
+                },
+                syn::parse_quote! {
+                    impl<'de> Deserialize<'de> for Vector2D {
+                        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                            let raw = <&'de str>::deserialize(deserializer)?;
+                            let mut floats = raw.split_whitespace().map(str::parse::<f32>);
+                            match (floats.next(), floats.next(), floats.next()) {
+                                (Some(Ok(x)), Some(Ok(y)), None) => Ok(Vector2D { x, y }),
+                                _ => Err(serde::de::Error::invalid_value(
+                                    serde::de::Unexpected::Str(raw),
+                                    &"a list of 2 numbers",
+                                )),
+                            }
+                        }
+                    }
+                },
+            ],
+            _ => vec![],
         }
     }
-    {
-        let count = values
-            .iter()
-            .flat_map(|&v| <f32 as EntityProp>::parse(v))
-            .count();
-        max_count = max_count.max(count);
-        counts.push((EntityPropertyType::F32, count));
-        if count == values.len() {
-            return EntityPropertyType::F32;
-        }
-    }
-    if name.find("color").is_some()
-        || name.find("light").is_some()
-        || name.find("ambient").is_some()
-    {
-        let count = values
-            .iter()
-            .flat_map(|&v| <Color as EntityProp>::parse(v))
-            .count();
-        max_count = max_count.max(count);
-        counts.push((EntityPropertyType::Color, count));
-        if count == values.len() {
-            return EntityPropertyType::Color;
+}
+
+/// Inputs failed to read and the failure policy doesn't tolerate it.
+#[derive(Debug)]
+struct ReadFailures {
+    failed: usize,
+    /// Reading stopped before every input was read.
+    aborted: bool,
+}
+impl std::fmt::Display for ReadFailures {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.aborted {
+            write!(f, "Aborted after {} inputs failed to read", self.failed)
+        } else {
+            write!(f, "{} inputs failed to read", self.failed)
         }
     }
-    {
-        let count = values
-            .iter()
-            .flat_map(|&v| <LightColor as EntityProp>::parse(v))
-            .count();
-        max_count = max_count.max(count);
-        counts.push((EntityPropertyType::LightColor, count));
-        if count == values.len() {
-            return EntityPropertyType::LightColor;
+}
+
+/// The result of reading all inputs with [`read_entities`].
+struct ReadSummary {
+    decoded: usize,
+    failures: Vec<(PathBuf, ReadBspError)>,
+    aborted: bool,
+    tolerated_failures: Option<usize>,
+}
+impl ReadSummary {
+    /// Fails when reading stopped early, the observations are incomplete.
+    fn aborted(&self) -> Result<(), ReadFailures> {
+        if self.aborted {
+            return Err(ReadFailures {
+                failed: self.failures.len(),
+                aborted: true,
+            });
         }
+        Ok(())
     }
-    if name.find("angles").is_some() || name.ends_with("dir") {
-        let count = values
-            .iter()
-            .flat_map(|&v| <Angles as EntityProp>::parse(v))
-            .count();
-        max_count = max_count.max(count);
-        counts.push((EntityPropertyType::Angles, count));
-        if count == values.len() {
-            return EntityPropertyType::Angles;
+
+    /// Fails when any input failed to read without the failures being tolerated,
+    /// or when every input failed.
+    fn finish(&self) -> Result<(), ReadFailures> {
+        self.aborted()?;
+        let tolerated = self.tolerated_failures.is_some() && self.decoded > 0;
+        if !self.failures.is_empty() && !tolerated {
+            return Err(ReadFailures {
+                failed: self.failures.len(),
+                aborted: false,
+            });
         }
+        Ok(())
     }
-    {
-        let count = values
-            .iter()
-            .flat_map(|&v| <Vector as EntityProp>::parse(v))
-            .count();
-        max_count = max_count.max(count);
-        counts.push((EntityPropertyType::Vector, count));
-        if count == values.len() {
-            return EntityPropertyType::Vector;
-        }
-    }
-    if 1 < values.len() && values.len() / 2 < max_count {
-        // why are there outliers that fail to parse?
-        let unique_values: HashSet<_> = values.iter().copied().collect();
-        println!("{name}: over 50% parsed, inspect outliers: {counts:?}\n{unique_values:?}",);
-
-        if values.len() as f32 * 0.99 < max_count as f32 {
-            println!("over 99% parsed, accepting even with outliers");
-            // This assumes that counts is sorted by EntityPropertyType.
-            for (ty, count) in counts {
-                if count == max_count {
-                    return ty;
+}
+
+/// Decode the maps in parallel, handing each entity lump to `on_entities` as soon as it is decoded.
+///
+/// Failures are reported as they happen and summarized at the end.
+fn read_entities(
+    input_files: Vec<PathBuf>,
+    args: &ReadArgs,
+    mut on_entities: impl FnMut(&Path, vbsp::Entities),
+) -> Result<ReadSummary, std::io::Error> {
+    let tolerated_failures = args.failure_policy.tolerated_failures();
+    let jobs = match args.jobs {
+        Some(jobs) => jobs,
+        None => std::thread::available_parallelism()?,
+    };
+    let mut decoded = 0;
+    let mut failures = Vec::new();
+    let completed = pipeline::read_parallel(
+        input_files,
+        jobs.get(),
+        |path| read_input_entities(path, args),
+        |path, result| {
+            match result.unwrap_or(Err(ReadBspError::Panicked)) {
+                Ok(entities) => {
+                    on_entities(&path, entities);
+                    decoded += 1;
+                }
+                Err(e) => {
+                    println!("File={:?} ReadBsp error: {}", path.file_stem(), e);
+                    failures.push((path, e));
                 }
             }
+            // keep going while the failures are tolerated
+            tolerated_failures.is_none_or(|tolerated| failures.len() <= tolerated)
+        },
+    );
+
+    if !failures.is_empty() {
+        println!("failed inputs={}:", failures.len());
+        for (path, e) in &failures {
+            println!("  {}: {e}", path.display());
         }
     }
-    EntityPropertyType::Str
-}
-
-struct ClassCollector<'a> {
-    occurrences: usize,
-    values: HashMap<&'a str, Vec<&'a str>>,
+    Ok(ReadSummary {
+        decoded,
+        failures,
+        aborted: !completed,
+        tolerated_failures,
+    })
 }
 
 #[allow(dead_code)]
 #[derive(Debug)]
 enum BspEntitiesError {
-    ReadBsp(ReadBspError),
+    SdkData(SdkDataError),
+    Schema(SchemaError),
+    ReadFailures(ReadFailures),
     Io(std::io::Error),
+    Preserve(preserve::PreserveError),
     Format(FormatError),
 }
+impl std::fmt::Display for BspEntitiesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BspEntitiesError::SdkData(error) => write!(f, "SdkData: {error}"),
+            BspEntitiesError::Schema(error) => write!(f, "Schema: {error}"),
+            BspEntitiesError::ReadFailures(failures) => write!(f, "{failures}"),
+            BspEntitiesError::Io(error) => write!(f, "Io: {error}"),
+            BspEntitiesError::Preserve(error) => write!(f, "Preserve: {error}"),
+            BspEntitiesError::Format(error) => write!(f, "Format: {error}"),
+        }
+    }
+}
+impl ExitError for BspEntitiesError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            BspEntitiesError::SdkData(_) | BspEntitiesError::Schema(_) => EXIT_SCHEMA,
+            BspEntitiesError::ReadFailures(_) => EXIT_READ_FAILURE,
+            BspEntitiesError::Io(_) => EXIT_OTHER,
+            BspEntitiesError::Preserve(_) => EXIT_OTHER,
+            BspEntitiesError::Format(_) => EXIT_FORMAT,
+        }
+    }
+}
 #[allow(dead_code)]
 #[derive(Debug)]
 enum FormatError {
     Io(std::io::Error),
     FormatFailed,
 }
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::Io(error) => write!(f, "Io: {error}"),
+            FormatError::FormatFailed => write!(f, "rustfmt failed"),
+        }
+    }
+}
 
 struct EntityCollector {
     structs: Vec<syn::ItemStruct>,
     variants: Vec<syn::Variant>,
+    used_types: std::collections::BTreeSet<EntityPropertyType>,
 }
 impl EntityCollector {
     fn new() -> Self {
         Self {
             structs: Vec::new(),
             variants: Vec::new(),
+            used_types: std::collections::BTreeSet::new(),
         }
     }
+    fn use_type(&mut self, ty: EntityPropertyType) {
+        self.used_types.insert(ty);
+    }
     fn push_entity(&mut self, classname: &str, props: Vec<syn::Field>, has_lifetime: bool) {
         // struct ident in UpperCamelCase
         let ident = syn::Ident::new(
@@ -472,6 +919,28 @@ impl EntityCollector {
             discriminant: None,
         });
     }
+    fn push_class(&mut self, class: &ClassSchema) {
+        let mut has_lifetime = false;
+        let mut props = Vec::new();
+        for key in &class.keys {
+            if key.ty.has_lifetime() {
+                has_lifetime = true;
+            }
+            self.use_type(key.ty);
+            props.push(key.ty.codegen(&key.name, key.optional));
+        }
+        // sort props for consistency
+        props.sort_by(|a, b| a.ident.cmp(&b.ident));
+
+        self.push_entity(&class.classname, props, has_lifetime);
+    }
+    fn from_schema(schema: &Schema) -> Self {
+        let mut entity_collector = EntityCollector::new();
+        for class in &schema.classes {
+            entity_collector.push_class(class);
+        }
+        entity_collector
+    }
     fn sort(&mut self) {
         self.structs.sort_by(|a, b| a.ident.cmp(&b.ident));
         self.variants.sort_by(|a, b| a.ident.cmp(&b.ident));
@@ -496,6 +965,10 @@ impl EntityCollector {
             use vbsp_common::deserialize_bool;
             use vbsp_common::{Angles, Color, LightColor, Negated, Vector};
         };
+        // define helper types used by the generated fields
+        for ty in &self.used_types {
+            complete_file.items.extend(ty.support_items());
+        }
         complete_file.items.push(syn::Item::Enum(entities_enum));
         complete_file
             .items
@@ -521,7 +994,33 @@ fn rustfmt(code: &[u8]) -> Result<Vec<u8>, FormatError> {
         return Err(FormatError::FormatFailed);
     }
 
-    Ok(output.stdout)
+    Ok(doc_comments(&output.stdout))
+}
+/// Token streams print doc comments as `#[doc = "..."]`, turn the simple ones back into `///`.
+fn doc_comments(code: &[u8]) -> Vec<u8> {
+    let Ok(code) = std::str::from_utf8(code) else {
+        return code.to_vec();
+    };
+    let mut result = String::with_capacity(code.len());
+    for line in code.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let indent = &line[..line.len() - trimmed.len()];
+        let doc = trimmed
+            .trim_end()
+            .strip_prefix("#[doc = \"")
+            .and_then(|doc| doc.strip_suffix("\"]"))
+            .filter(|doc| !doc.contains(['\\', '"']));
+        match doc {
+            Some(doc) => {
+                result.push_str(indent);
+                result.push_str("///");
+                result.push_str(doc);
+                result.push('\n');
+            }
+            None => result.push_str(line),
+        }
+    }
+    result.into_bytes()
 }
 fn write_dest(code: &[u8], dest: PathBuf) -> Result<(), std::io::Error> {
     let mut file = std::fs::File::create(dest)?;
@@ -529,110 +1028,146 @@ fn write_dest(code: &[u8], dest: PathBuf) -> Result<(), std::io::Error> {
     Ok(())
 }
 
-fn bsp_entities(paths: Vec<PathBuf>, dest: PathBuf) -> Result<(), BspEntitiesError> {
-    let sdk_data = SdkData::new();
-
-    let start = std::time::Instant::now();
+/// Choose a type for every key observed in the collected classes.
+fn infer_schema(observations: &Observations, sdk_data: &SdkData) -> Schema {
+    let mut schema = Schema::default();
+    for (classname, properties) in &observations.classes {
+        let sdk_types = sdk_data.types_for_entity(classname);
 
-    // decode bsps in parallel using available_parallelism
-    let bsps_entities = {
-        let mut bsps = Vec::with_capacity(paths.len());
-
-        let thread_limit = std::thread::available_parallelism()
-            .map_err(BspEntitiesError::Io)?
-            .get();
-        let mut threads = std::collections::VecDeque::with_capacity(thread_limit);
-        type Thread = std::thread::JoinHandle<(PathBuf, Result<vbsp::Bsp, ReadBspError>)>;
-        let mut join_thread = |thread: Thread| match thread.join() {
-            Ok((_, Ok(bsp))) => Ok(bsps.push(bsp.entities)),
-            Ok((path, Err(e))) => Ok(println!("File={:?} ReadBsp error: {}", path.file_stem(), e)),
-            Err(e) => Err(e),
-        };
+        let mut keys = Vec::new();
+        for (propname, values) in &properties.keys {
+            // this is an optional type and should have a default value
+            let optional = values.entities < properties.occurrences;
+
+            let (ty, provenance) = match sdk_types.get(propname.as_str()) {
+                // the sdk only knows model as a string, brush entities use a model index
+                Some(EntityPropertyType::Str | EntityPropertyType::ModelName)
+                    if propname == "model" =>
+                {
+                    (values.minimal_type(propname), Provenance::Inferred)
+                }
+                Some(&sdk_type) => (sdk_type, Provenance::Sdk),
+                // make sure all observed values can be parsed by the chosen type
+                None => (values.minimal_type(propname), Provenance::Inferred),
+            };
 
-        for path in paths {
-            if thread_limit <= threads.len() {
-                join_thread(threads.pop_front().unwrap()).unwrap();
-            }
-            threads.push_back(std::thread::spawn(|| (path.clone(), read_bsp(path))));
+            keys.push(KeySchema {
+                name: propname.clone(),
+                ty,
+                optional,
+                count: values.count,
+                type_counts: values.type_counts.clone(),
+                provenance,
+            });
         }
 
-        for thread in threads {
-            join_thread(thread).unwrap();
-        }
+        schema.classes.push(ClassSchema {
+            sdk_class: sdk_data.class_for_entity(classname).map(str::to_owned),
+            classname: classname.clone(),
+            occurrences: properties.occurrences,
+            keys,
+        });
+    }
+    schema.sort();
+    schema
+}
 
-        bsps
-    };
+/// The schema of every entity class in the sdk data.
+fn sdk_schema(sdk_data: &SdkData) -> Schema {
+    let mut schema = Schema::default();
+    for (classname, properties) in sdk_data.entities() {
+        let keys = properties
+            .into_iter()
+            .map(|(propname, ty)| KeySchema {
+                name: propname.to_owned(),
+                ty,
+                optional: false,
+                count: 0,
+                type_counts: Default::default(),
+                provenance: Provenance::Sdk,
+            })
+            .collect();
+        schema.classes.push(ClassSchema {
+            classname: classname.to_owned(),
+            occurrences: 0,
+            sdk_class: sdk_data.class_for_entity(classname).map(str::to_owned),
+            keys,
+        });
+    }
+    schema.sort();
+    schema
+}
 
-    let decode_elapsed = start.elapsed();
-    println!(
-        "bsps decoded={} elapsed={:?}",
-        bsps_entities.len(),
-        decode_elapsed
-    );
+fn bsp_entities(command: GenerateSubcommand) -> Result<(), BspEntitiesError> {
+    let sdk_args = &command.sdk_data;
+    let sdk_layers = sdk_args.layers().map_err(BspEntitiesError::SdkData)?;
+    let sdk_data = SdkData::load(&sdk_layers, sdk_args.unknown_type_policy())
+        .map_err(BspEntitiesError::SdkData)?;
 
-    let start_generate = std::time::Instant::now();
+    let start = std::time::Instant::now();
 
-    // collect observed class instances
-    let mut classes = std::collections::HashMap::new();
-    for entities in &bsps_entities {
-        for ent in entities {
-            if let Some(class) = ent.prop("classname") {
-                if class == "" {
-                    println!("empty class ident! class={class}");
-                    continue;
-                }
-                let props = classes.entry(class).or_insert(ClassCollector {
-                    occurrences: 0,
-                    values: HashMap::new(),
-                });
-                props.occurrences += 1;
-                for (name, value) in ent.properties() {
-                    if matches!(name, "classname" | "hammerid") {
-                        continue;
-                    }
-                    if name == "" {
-                        println!("empty ident! class={class} value={value}");
-                        continue;
-                    }
-                    let values = props.values.entry(name).or_insert(Vec::new());
-                    // observed value string
-                    values.push(value);
-                }
-            } else {
-                println!("No classname in entity! {ent:?}");
-            }
+    // the FGD and JSON Schema list the observed values as choices, `docs` always keeps them for its examples
+    let value_stats = command.fgd_output.is_some() || command.json_schema_output.is_some();
+    let mut observations = if value_stats {
+        Observations::with_value_stats()
+    } else {
+        Observations::default()
+    };
+    let mut inputs = fgd::InputCollector::default();
+    // decode bsps in parallel,
+    // folding each map into the observations as soon as it is decoded
+    let summary = read_entities(command.input_files, &command.read, |_, entities| {
+        observations.add_entities(&entities);
+        if command.fgd_output.is_some() {
+            inputs.add_entities(&entities);
         }
-    }
+    })
+    .map_err(BspEntitiesError::Io)?;
+    summary.aborted().map_err(BspEntitiesError::ReadFailures)?;
+    let decoded = summary.decoded;
 
-    // generate a struct for each entity
-    let mut entity_collector = EntityCollector::new();
-    for (classname, properties) in classes {
-        let sdk_types = sdk_data.types_for_entity(classname);
-
-        let mut has_lifetime = false;
-        let mut props = Vec::new();
-        for (propname, values) in properties.values {
-            // this is an optional type and should have a default value
-            let optional = values.len() < properties.occurrences;
+    let decode_elapsed = start.elapsed();
+    println!("bsps decoded={decoded} elapsed={decode_elapsed:?}");
 
-            let ty = match sdk_types.get(&propname) {
-                Some(&sdk_type) => sdk_type,
-                // exhaustively make sure all observed values can be parsed by the chosen type
-                None => get_minimal_type(propname, &values),
-            };
-            if matches!(ty, EntityPropertyType::Str) {
-                has_lifetime = true;
-            }
+    let start_generate = std::time::Instant::now();
 
-            props.push(ty.codegen(propname, optional));
-        }
-        // sort props for consistency
-        props.sort_by(|a, b| a.ident.cmp(&b.ident));
+    let mut schema = infer_schema(&observations, &sdk_data);
+    if let Some(base_schema) = command.merge_schema {
+        let mut base = Schema::read(&base_schema).map_err(BspEntitiesError::Schema)?;
+        base.merge(schema).map_err(BspEntitiesError::Schema)?;
+        schema = base;
+    }
+    if let Some(schema_dest) = command.schema_output {
+        schema
+            .write(&schema_dest)
+            .map_err(BspEntitiesError::Schema)?;
+    }
+    if let Some(fgd_dest) = &command.fgd_output {
+        let mut out =
+            std::io::BufWriter::new(std::fs::File::create(fgd_dest).map_err(BspEntitiesError::Io)?);
+        fgd::write_fgd(&mut out, &schema, &observations, &inputs)
+            .and_then(|()| out.flush())
+            .map_err(BspEntitiesError::Io)?;
+    }
+    if let Some(json_schema_dest) = &command.json_schema_output {
+        let json_schema = jsonschema::json_schema(&schema, &observations);
+        let json_schema = serde_json::to_string_pretty(&json_schema).map_err(std::io::Error::from);
+        json_schema
+            .and_then(|json_schema| std::fs::write(json_schema_dest, json_schema + "\n"))
+            .map_err(BspEntitiesError::Io)?;
+    }
 
-        entity_collector.push_entity(classname, props, has_lifetime);
+    if let Some(typescript_dest) = &command.typescript_output {
+        std::fs::write(typescript_dest, typescript::typescript(&schema))
+            .map_err(BspEntitiesError::Io)?;
     }
 
-    let complete_file = entity_collector.codegen();
+    // generate a struct for each entity
+    let mut complete_file = EntityCollector::from_schema(&schema).codegen();
+    if command.keep_edits {
+        complete_file = preserve::merge_existing(&command.output_file, complete_file)
+            .map_err(BspEntitiesError::Preserve)?;
+    }
 
     // time!
     let generate_elapsed = start_generate.elapsed();
@@ -648,7 +1183,7 @@ fn bsp_entities(paths: Vec<PathBuf>, dest: PathBuf) -> Result<(), BspEntitiesErr
     let start_output = std::time::Instant::now();
 
     // save to destination file
-    write_dest(&code, dest).map_err(BspEntitiesError::Io)?;
+    write_dest(&code, command.output_file).map_err(BspEntitiesError::Io)?;
 
     let output_elapsed = start_output.elapsed();
     let elapsed = start.elapsed();
@@ -660,38 +1195,66 @@ fn bsp_entities(paths: Vec<PathBuf>, dest: PathBuf) -> Result<(), BspEntitiesErr
     println!("format elapsed={format_elapsed:?}");
     println!("output elapsed={output_elapsed:?}");
     println!("total elapsed={elapsed:?}");
-    Ok(())
+
+    // the output is complete, but the run is only successful if the failures are tolerated
+    summary.finish().map_err(BspEntitiesError::ReadFailures)
 }
 
 #[allow(dead_code)]
 #[derive(Debug)]
 enum SDKEntitiesError {
+    SdkData(SdkDataError),
+    Schema(SchemaError),
     Io(std::io::Error),
+    Preserve(preserve::PreserveError),
     Format(FormatError),
 }
-fn sdk_entities(dest: PathBuf) -> Result<(), SDKEntitiesError> {
+impl std::fmt::Display for SDKEntitiesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SDKEntitiesError::SdkData(error) => write!(f, "SdkData: {error}"),
+            SDKEntitiesError::Schema(error) => write!(f, "Schema: {error}"),
+            SDKEntitiesError::Io(error) => write!(f, "Io: {error}"),
+            SDKEntitiesError::Preserve(error) => write!(f, "Preserve: {error}"),
+            SDKEntitiesError::Format(error) => write!(f, "Format: {error}"),
+        }
+    }
+}
+impl ExitError for SDKEntitiesError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            SDKEntitiesError::SdkData(_) | SDKEntitiesError::Schema(_) => EXIT_SCHEMA,
+            SDKEntitiesError::Io(_) => EXIT_OTHER,
+            SDKEntitiesError::Preserve(_) => EXIT_OTHER,
+            SDKEntitiesError::Format(_) => EXIT_FORMAT,
+        }
+    }
+}
+fn sdk_entities(
+    dest: PathBuf,
+    keep_edits: bool,
+    schema_dest: Option<PathBuf>,
+    sdk_args: SdkDataArgs,
+) -> Result<(), SDKEntitiesError> {
     let start = std::time::Instant::now();
-    let sdk_data = SdkData::new();
+    let sdk_layers = sdk_args.layers().map_err(SDKEntitiesError::SdkData)?;
+    let sdk_data = SdkData::load(&sdk_layers, sdk_args.unknown_type_policy())
+        .map_err(SDKEntitiesError::SdkData)?;
     let decode_elapsed = start.elapsed();
     let start_generate = std::time::Instant::now();
-    // generate a struct for each entity
-    let mut entity_collector = EntityCollector::new();
-    for (classname, properties) in sdk_data.entities() {
-        let mut has_lifetime = false;
-        let mut props = Vec::new();
-        for (propname, ty) in properties {
-            if matches!(ty, EntityPropertyType::Str) {
-                has_lifetime = true;
-            }
-            props.push(ty.codegen(propname, false));
-        }
-        // sort props for consistency
-        props.sort_by(|a, b| a.ident.cmp(&b.ident));
-
-        entity_collector.push_entity(classname, props, has_lifetime);
+    let schema = sdk_schema(&sdk_data);
+    if let Some(schema_dest) = schema_dest {
+        schema
+            .write(&schema_dest)
+            .map_err(SDKEntitiesError::Schema)?;
     }
 
-    let complete_file = entity_collector.codegen();
+    // generate a struct for each entity
+    let mut complete_file = EntityCollector::from_schema(&schema).codegen();
+    if keep_edits {
+        complete_file =
+            preserve::merge_existing(&dest, complete_file).map_err(SDKEntitiesError::Preserve)?;
+    }
 
     // time!
     let generate_elapsed = start_generate.elapsed();
@@ -720,43 +1283,608 @@ fn sdk_entities(dest: PathBuf) -> Result<(), SDKEntitiesError> {
     Ok(())
 }
 
-// auxilliary function to sort existing structs
-fn _sort_structs() {
-    let mut file: syn::File = syn::parse_quote! {
-        // PASTE STRUCTS HERE TO SORT THEM
+#[allow(dead_code)]
+#[derive(Debug)]
+enum EmitEntitiesError {
+    Schema(SchemaError),
+    Io(std::io::Error),
+    Preserve(preserve::PreserveError),
+    Format(FormatError),
+}
+impl std::fmt::Display for EmitEntitiesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmitEntitiesError::Schema(error) => write!(f, "Schema: {error}"),
+            EmitEntitiesError::Io(error) => write!(f, "Io: {error}"),
+            EmitEntitiesError::Preserve(error) => write!(f, "Preserve: {error}"),
+            EmitEntitiesError::Format(error) => write!(f, "Format: {error}"),
+        }
+    }
+}
+impl ExitError for EmitEntitiesError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            EmitEntitiesError::Schema(_) => EXIT_SCHEMA,
+            EmitEntitiesError::Io(_) => EXIT_OTHER,
+            EmitEntitiesError::Preserve(_) => EXIT_OTHER,
+            EmitEntitiesError::Format(_) => EXIT_FORMAT,
+        }
+    }
+}
+fn emit_entities(
+    schema_path: PathBuf,
+    dest: PathBuf,
+    keep_edits: bool,
+) -> Result<(), EmitEntitiesError> {
+    let start = std::time::Instant::now();
+    let schema = Schema::read(&schema_path).map_err(EmitEntitiesError::Schema)?;
+    let decode_elapsed = start.elapsed();
+    let start_generate = std::time::Instant::now();
+
+    // generate a struct for each entity
+    let mut complete_file = EntityCollector::from_schema(&schema).codegen();
+    if keep_edits {
+        complete_file =
+            preserve::merge_existing(&dest, complete_file).map_err(EmitEntitiesError::Preserve)?;
+    }
+
+    // time!
+    let generate_elapsed = start_generate.elapsed();
+    let start_format = std::time::Instant::now();
+
+    // make a string of the unformatted code
+    let code = complete_file.into_token_stream().to_string();
+
+    // format via cli
+    let code = rustfmt(code.as_bytes()).map_err(EmitEntitiesError::Format)?;
+
+    let format_elapsed = start_format.elapsed();
+    let start_output = std::time::Instant::now();
+
+    // save to destination file
+    write_dest(&code, dest).map_err(EmitEntitiesError::Io)?;
+
+    let output_elapsed = start_output.elapsed();
+    let elapsed = start.elapsed();
+
+    println!("schema decode elapsed={decode_elapsed:?}");
+    println!("generate elapsed={generate_elapsed:?}");
+    println!("format elapsed={format_elapsed:?}");
+    println!("output elapsed={output_elapsed:?}");
+    println!("total elapsed={elapsed:?}");
+    Ok(())
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+enum CheckEntitiesError {
+    Io(std::io::Error),
+    Generated(check::GeneratedError),
+    ReadFailures(ReadFailures),
+    /// Entities that would fail to deserialize or lose keyvalues.
+    Problems {
+        entities: usize,
+    },
+}
+impl std::fmt::Display for CheckEntitiesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckEntitiesError::Io(error) => write!(f, "Io: {error}"),
+            CheckEntitiesError::Generated(error) => write!(f, "Generated: {error}"),
+            CheckEntitiesError::ReadFailures(failures) => write!(f, "{failures}"),
+            CheckEntitiesError::Problems { entities } => {
+                write!(f, "{entities} entities don't match the generated code")
+            }
+        }
+    }
+}
+impl ExitError for CheckEntitiesError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            CheckEntitiesError::Io(_) => EXIT_OTHER,
+            CheckEntitiesError::Generated(_) => EXIT_SCHEMA,
+            CheckEntitiesError::ReadFailures(_) => EXIT_READ_FAILURE,
+            CheckEntitiesError::Problems { .. } => EXIT_CHECK_FAILED,
+        }
+    }
+}
+
+fn check_entities(command: CheckSubcommand) -> Result<(), CheckEntitiesError> {
+    let source = std::fs::read_to_string(&command.schema).map_err(CheckEntitiesError::Io)?;
+    let schema = check::parse_generated(&source).map_err(CheckEntitiesError::Generated)?;
+
+    let mut reports = Vec::new();
+    let summary = read_entities(command.input_files, &command.read, |path, entities| {
+        let mut lines = Vec::new();
+        let mut failed = 0;
+        for (index, entity) in entities.iter().enumerate() {
+            let problems = check::check_entity(&schema, &entity);
+            if !problems.is_empty() {
+                failed += 1;
+            }
+            for problem in problems {
+                let classname = entity.prop("classname").unwrap_or_default();
+                lines.push(format!("entity={index} class={classname}: {problem}"));
+            }
+        }
+        reports.push((path.to_owned(), failed, lines));
+    })
+    .map_err(CheckEntitiesError::Io)?;
+
+    // maps complete in any order, report them in a stable one
+    reports.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut entities = 0;
+    let mut problems = 0;
+    for (path, failed, lines) in &reports {
+        for line in lines {
+            println!("{}: {line}", path.display());
+        }
+        entities += failed;
+        problems += lines.len();
+    }
+    println!(
+        "bsps checked={} entities={entities} problems={problems}",
+        summary.decoded
+    );
+
+    summary.finish().map_err(CheckEntitiesError::ReadFailures)?;
+    if entities != 0 {
+        return Err(CheckEntitiesError::Problems { entities });
+    }
+    Ok(())
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+enum NormalizeError {
+    Io(std::io::Error),
+    Generated(check::GeneratedError),
+}
+impl std::fmt::Display for NormalizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NormalizeError::Io(error) => write!(f, "Io: {error}"),
+            NormalizeError::Generated(error) => write!(f, "Generated: {error}"),
+        }
+    }
+}
+impl ExitError for NormalizeError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            NormalizeError::Io(_) => EXIT_OTHER,
+            NormalizeError::Generated(_) => EXIT_SCHEMA,
+        }
+    }
+}
+
+fn normalize_file(command: NormalizeSubcommand) -> Result<(), NormalizeError> {
+    let source = std::fs::read_to_string(&command.file).map_err(NormalizeError::Io)?;
+    let normalized = normalize::normalize(&source).map_err(NormalizeError::Generated)?;
+    if command.stdout {
+        std::io::stdout()
+            .write_all(normalized.as_bytes())
+            .map_err(NormalizeError::Io)?;
+    } else if normalized != source {
+        write_dest(normalized.as_bytes(), command.file).map_err(NormalizeError::Io)?;
+    }
+    Ok(())
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+enum DiffError {
+    SdkData(SdkDataError),
+    Schema(SchemaError),
+    Generated(check::GeneratedError),
+    ReadFailures(ReadFailures),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+impl std::fmt::Display for DiffError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffError::SdkData(error) => write!(f, "SdkData: {error}"),
+            DiffError::Schema(error) => write!(f, "Schema: {error}"),
+            DiffError::Generated(error) => write!(f, "Generated: {error}"),
+            DiffError::ReadFailures(failures) => write!(f, "{failures}"),
+            DiffError::Io(error) => write!(f, "Io: {error}"),
+            DiffError::Json(error) => write!(f, "Json: {error}"),
+        }
+    }
+}
+impl ExitError for DiffError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            DiffError::SdkData(_) | DiffError::Schema(_) | DiffError::Generated(_) => EXIT_SCHEMA,
+            DiffError::ReadFailures(_) => EXIT_READ_FAILURE,
+            DiffError::Io(_) | DiffError::Json(_) => EXIT_OTHER,
+        }
+    }
+}
+
+/// The schema of one side of a diff.
+fn diff_side(
+    paths: Vec<PathBuf>,
+    sdk_data: &SdkData,
+    read: &ReadArgs,
+) -> Result<Schema, DiffError> {
+    if let [path] = paths.as_slice() {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("rs") => {
+                let source = std::fs::read_to_string(path).map_err(DiffError::Io)?;
+                return check::parse_generated(&source).map_err(DiffError::Generated);
+            }
+            Some("json") => return Schema::read(path).map_err(DiffError::Schema),
+            _ => {}
+        }
+    }
+    let mut observations = Observations::default();
+    let summary = read_entities(paths, read, |_, entities| {
+        observations.add_entities(&entities)
+    })
+    .map_err(DiffError::Io)?;
+    summary.finish().map_err(DiffError::ReadFailures)?;
+    Ok(infer_schema(&observations, sdk_data))
+}
+
+fn diff_schemas(command: DiffSubcommand) -> Result<(), DiffError> {
+    let sdk_args = &command.sdk_data;
+    let sdk_layers = sdk_args.layers().map_err(DiffError::SdkData)?;
+    let sdk_data =
+        SdkData::load(&sdk_layers, sdk_args.unknown_type_policy()).map_err(DiffError::SdkData)?;
+    let old = diff_side(command.old, &sdk_data, &command.read)?;
+    let new = diff_side(command.new, &sdk_data, &command.read)?;
+    let changes = diff::diff(&old, &new);
+
+    let mut out: Box<dyn Write> = match command.output_file {
+        Some(path) => Box::new(std::fs::File::create(path).map_err(DiffError::Io)?),
+        None => Box::new(std::io::stdout()),
     };
+    if command.json {
+        serde_json::to_writer_pretty(&mut out, &changes).map_err(DiffError::Json)?;
+        writeln!(out).map_err(DiffError::Io)?;
+    } else {
+        for change in &changes {
+            writeln!(out, "{change}").map_err(DiffError::Io)?;
+        }
+    }
+    Ok(())
+}
 
-    for item in &mut file.items {
-        if let syn::Item::Struct(s) = item {
-            let mut fields: Vec<_> = s.fields.iter().cloned().collect();
-            fields.sort_by(|a, b| a.ident.cmp(&b.ident));
-            s.fields = syn::Fields::Named(syn::FieldsNamed {
-                brace_token: syn::token::Brace::default(),
-                named: fields.into_iter().collect(),
-            });
+#[allow(dead_code)]
+#[derive(Debug)]
+enum StatsError {
+    SdkData(SdkDataError),
+    ReadFailures(ReadFailures),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+impl std::fmt::Display for StatsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatsError::SdkData(error) => write!(f, "SdkData: {error}"),
+            StatsError::ReadFailures(failures) => write!(f, "{failures}"),
+            StatsError::Io(error) => write!(f, "Io: {error}"),
+            StatsError::Json(error) => write!(f, "Json: {error}"),
+        }
+    }
+}
+impl ExitError for StatsError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            StatsError::SdkData(_) => EXIT_SCHEMA,
+            StatsError::ReadFailures(_) => EXIT_READ_FAILURE,
+            StatsError::Io(_) | StatsError::Json(_) => EXIT_OTHER,
         }
     }
+}
+
+fn corpus_stats(command: StatsSubcommand) -> Result<(), StatsError> {
+    let sdk_args = &command.sdk_data;
+    let sdk_layers = sdk_args.layers().map_err(StatsError::SdkData)?;
+    let sdk_data =
+        SdkData::load(&sdk_layers, sdk_args.unknown_type_policy()).map_err(StatsError::SdkData)?;
+
+    let mut observations = Observations::with_value_stats();
+    let summary = read_entities(command.input_files, &command.read, |_, entities| {
+        observations.add_entities(&entities)
+    })
+    .map_err(StatsError::Io)?;
+    summary.aborted().map_err(StatsError::ReadFailures)?;
+
+    let schema = infer_schema(&observations, &sdk_data);
+    let stats = stats::class_stats(&observations, &schema, command.top);
+
+    let mut out: Box<dyn Write> = match command.output_file {
+        Some(path) => Box::new(std::fs::File::create(path).map_err(StatsError::Io)?),
+        None => Box::new(std::io::stdout()),
+    };
+    if command.json {
+        serde_json::to_writer_pretty(&mut out, &stats).map_err(StatsError::Json)?;
+        writeln!(out).map_err(StatsError::Io)?;
+    } else {
+        stats::write_table(&mut out, &stats).map_err(StatsError::Io)?;
+    }
+    summary.finish().map_err(StatsError::ReadFailures)
+}
 
-    file.items.sort_by(|a, b| {
-        if let (syn::Item::Struct(a), syn::Item::Struct(b)) = (a, b) {
-            a.ident.cmp(&b.ident)
-        } else {
-            panic!();
+#[allow(dead_code)]
+#[derive(Debug)]
+enum QueryError {
+    ReadFailures(ReadFailures),
+    Io(std::io::Error),
+}
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::ReadFailures(failures) => write!(f, "{failures}"),
+            QueryError::Io(error) => write!(f, "Io: {error}"),
+        }
+    }
+}
+impl ExitError for QueryError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            QueryError::ReadFailures(_) => EXIT_READ_FAILURE,
+            QueryError::Io(_) => EXIT_OTHER,
         }
-    });
+    }
+}
 
-    println!("{}", file.into_token_stream().to_string());
+fn query_entities(command: QuerySubcommand) -> Result<(), QueryError> {
+    let mut results = Vec::new();
+    let summary = read_entities(command.input_files, &command.read, |path, entities| {
+        let mut matches = Vec::new();
+        for (index, entity) in entities.iter().enumerate() {
+            if query::matches(&command.filters, &entity) {
+                let properties: Vec<_> = entity
+                    .properties()
+                    .map(|(key, value)| format!("  {key:?} {value:?}"))
+                    .collect();
+                matches.push((index, properties));
+            }
+        }
+        results.push((path.to_owned(), matches));
+    })
+    .map_err(QueryError::Io)?;
+
+    // maps complete in any order, print them in a stable one
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut count = 0;
+    for (path, matches) in &results {
+        for (index, properties) in matches {
+            println!("{}: entity={index}", path.display());
+            for property in properties {
+                println!("{property}");
+            }
+        }
+        count += matches.len();
+    }
+    println!("bsps searched={} matches={count}", summary.decoded);
+    summary.finish().map_err(QueryError::ReadFailures)
 }
 
-// auxilliary function to sort existing enum variants
-fn _sort_enum() {
-    let mut entities_enum: syn::ItemEnum = syn::parse_quote! {
-        // PASTE ENUM HERE TO SORT VARIANTS
+#[allow(dead_code)]
+#[derive(Debug)]
+enum DumpError {
+    ReadFailures(ReadFailures),
+    Io(std::io::Error),
+    /// Two inputs would be written to the same file.
+    Collision(PathBuf, PathBuf),
+}
+impl std::fmt::Display for DumpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DumpError::ReadFailures(failures) => write!(f, "{failures}"),
+            DumpError::Io(error) => write!(f, "Io: {error}"),
+            DumpError::Collision(first, second) => write!(
+                f,
+                "{} and {} would be written to the same file, dump them separately or with --combined",
+                first.display(),
+                second.display()
+            ),
+        }
+    }
+}
+impl ExitError for DumpError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            DumpError::ReadFailures(_) => EXIT_READ_FAILURE,
+            DumpError::Io(_) | DumpError::Collision(..) => EXIT_OTHER,
+        }
+    }
+}
+
+fn dump_entities(mut command: DumpSubcommand) -> Result<(), DumpError> {
+    // dumps are edited and written back with `patch`
+    command.read.preserve_case = !command.lowercase;
+    if !command.combined {
+        if let Some((first, second)) = dump::map_file_collision(&command.input_files) {
+            return Err(DumpError::Collision(first.to_owned(), second.to_owned()));
+        }
+    }
+    let mut dumper = dump::Dumper::new(&command.output_dir, command.format, command.combined)
+        .map_err(DumpError::Io)?;
+    // the first write error, reading continues but nothing more is written
+    let mut write_error = None;
+    let summary = read_entities(command.input_files, &command.read, |path, entities| {
+        if write_error.is_none() {
+            write_error = dumper.add(path, &entities).err();
+        }
+    })
+    .map_err(DumpError::Io)?;
+    if let Some(error) = write_error {
+        return Err(DumpError::Io(error));
+    }
+    summary.aborted().map_err(DumpError::ReadFailures)?;
+    dumper.finish().map_err(DumpError::Io)?;
+    println!("bsps dumped={}", summary.decoded);
+    summary.finish().map_err(DumpError::ReadFailures)
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+enum PatchMapError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// A key or value that can't be written in keyvalue text.
+    KeyValue {
+        entity: usize,
+        key: String,
+    },
+    Patch(patch::PatchError),
+}
+impl std::fmt::Display for PatchMapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchMapError::Io(error) => write!(f, "Io: {error}"),
+            PatchMapError::Json(error) => write!(f, "Json: {error}"),
+            PatchMapError::KeyValue { entity, key } => write!(
+                f,
+                "entity {entity}: key {key:?} or its value contains a quote, brace or line break"
+            ),
+            PatchMapError::Patch(error) => write!(f, "Patch: {error}"),
+        }
+    }
+}
+impl ExitError for PatchMapError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            PatchMapError::Io(_) => EXIT_OTHER,
+            // the map itself is broken
+            PatchMapError::Patch(patch::PatchError::Lump(_)) => EXIT_READ_FAILURE,
+            PatchMapError::Json(_)
+            | PatchMapError::KeyValue { .. }
+            | PatchMapError::Patch(
+                patch::PatchError::UnknownFormat | patch::PatchError::NoLumpPatches(_),
+            ) => EXIT_INVALID_PATCH,
+        }
+    }
+}
+
+/// Check that every key and value stays within its quotes, the lump has no escapes.
+fn check_keyvalues<K: AsRef<str>, V: AsRef<str>>(
+    entities: &[Vec<(K, V)>],
+) -> Result<(), PatchMapError> {
+    let invalid = |text: &str| text.contains(['"', '{', '}', '\n', '\r', '\0']);
+    for (index, entity) in entities.iter().enumerate() {
+        if let Some((key, _)) = entity
+            .iter()
+            .find(|(key, value)| invalid(key.as_ref()) || invalid(value.as_ref()))
+        {
+            return Err(PatchMapError::KeyValue {
+                entity: index,
+                key: key.as_ref().to_owned(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The entity lump text of keyvalue text or `dump` json, written again the way compilers do.
+fn read_entity_file(path: &Path) -> Result<(usize, String), PatchMapError> {
+    let source = std::fs::read(path).map_err(PatchMapError::Io)?;
+    let is_json = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+    if is_json {
+        let entities: Vec<Vec<(String, String)>> =
+            serde_json::from_slice(&source).map_err(PatchMapError::Json)?;
+        check_keyvalues(&entities)?;
+        return Ok((entities.len(), lump::format_entities(entities)));
+    }
+    let entities = vbsp::Entities {
+        entities: String::from_utf8_lossy(&source).into_owned(),
     };
+    let entities: Vec<Vec<(&str, &str)>> = entities
+        .iter()
+        .map(|entity| entity.properties().collect())
+        .collect();
+    check_keyvalues(&entities)?;
+    Ok((entities.len(), lump::format_entities(entities)))
+}
+
+/// Write through a temporary file next to `dest`, so a failed write leaves `dest` as it was.
+fn write_replacing(dest: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    let temp = dest.with_file_name(format!(".{name}.tmp"));
+    let written = std::fs::write(&temp, bytes).and_then(|()| std::fs::rename(&temp, dest));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    written
+}
 
-    let mut variants: Vec<_> = entities_enum.variants.iter().cloned().collect();
-    variants.sort_by(|a, b| a.ident.cmp(&b.ident));
-    entities_enum.variants = variants.into_iter().collect();
+fn patch_map(command: PatchSubcommand) -> Result<(), PatchMapError> {
+    let (count, entities) = read_entity_file(&command.entities)?;
+    let bsp = std::fs::read(&command.bsp).map_err(PatchMapError::Io)?;
+    let (patched, dest) = if command.lmp {
+        let dest = command
+            .output_file
+            .unwrap_or_else(|| lump::next_patch_file(&command.bsp));
+        (patch::entity_lmp(&bsp, &entities), dest)
+    } else {
+        let dest = command.output_file.unwrap_or(command.bsp);
+        (patch::replace_entity_lump(&bsp, &entities), dest)
+    };
+    let patched = patched.map_err(PatchMapError::Patch)?;
+    write_replacing(&dest, &patched).map_err(PatchMapError::Io)?;
+    println!("{}: entities={count}", dest.display());
+    Ok(())
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+enum DocsError {
+    SdkData(SdkDataError),
+    ReadFailures(ReadFailures),
+    Io(std::io::Error),
+}
+impl std::fmt::Display for DocsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DocsError::SdkData(error) => write!(f, "SdkData: {error}"),
+            DocsError::ReadFailures(failures) => write!(f, "{failures}"),
+            DocsError::Io(error) => write!(f, "Io: {error}"),
+        }
+    }
+}
+impl ExitError for DocsError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            DocsError::SdkData(_) => EXIT_SCHEMA,
+            DocsError::ReadFailures(_) => EXIT_READ_FAILURE,
+            DocsError::Io(_) => EXIT_OTHER,
+        }
+    }
+}
 
-    println!("{}", entities_enum.into_token_stream().to_string());
+fn entity_docs(command: DocsSubcommand) -> Result<(), DocsError> {
+    let sdk_args = &command.sdk_data;
+    let sdk_layers = sdk_args.layers().map_err(DocsError::SdkData)?;
+    let sdk_data =
+        SdkData::load(&sdk_layers, sdk_args.unknown_type_policy()).map_err(DocsError::SdkData)?;
+
+    let mut observations = Observations::with_value_stats();
+    let mut maps = docs::MapIndex::default();
+    let summary = read_entities(command.input_files, &command.read, |path, entities| {
+        observations.add_entities(&entities);
+        maps.add_entities(path, &entities);
+    })
+    .map_err(DocsError::Io)?;
+    summary.aborted().map_err(DocsError::ReadFailures)?;
+
+    let schema = infer_schema(&observations, &sdk_data);
+    let stats = stats::class_stats(&observations, &schema, command.examples);
+    docs::write_docs(
+        &command.output_dir,
+        command.format,
+        &schema,
+        &stats,
+        &sdk_data,
+        &maps,
+    )
+    .map_err(DocsError::Io)?;
+    println!("classes documented={}", schema.classes.len());
+    summary.finish().map_err(DocsError::ReadFailures)
 }
@@ -0,0 +1,161 @@
+use crate::check::GeneratedError;
+use proc_macro2::{LineColumn, Span};
+use std::ops::Range;
+use syn::spanned::Spanned;
+
+/// Converts span locations into byte offsets of the source.
+struct Offsets<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Offsets<'a> {
+    fn new(source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    fn offset(&self, location: LineColumn) -> usize {
+        // lines are 1-based, columns count chars
+        let start = self.line_starts[location.line - 1];
+        self.source[start..]
+            .char_indices()
+            .nth(location.column)
+            .map_or(self.source.len(), |(i, _)| start + i)
+    }
+
+    fn start(&self, span: Span) -> usize {
+        self.offset(span.start())
+    }
+
+    fn end(&self, span: Span) -> usize {
+        self.offset(span.end())
+    }
+}
+
+/// Move the text of the elements into sorted order, the text between them stays in place.
+///
+/// Each chunk includes the comments and whitespace in front of the element, so they move along.
+fn reorder<K: Ord>(
+    source: &str,
+    elements: Vec<(Range<usize>, K)>,
+    edits: &mut Vec<(Range<usize>, String)>,
+) {
+    let mut sorted: Vec<_> = elements.iter().collect();
+    sorted.sort_by(|a, b| a.1.cmp(&b.1));
+    for ((slot, _), (chunk, _)) in elements.iter().zip(sorted) {
+        if slot != chunk {
+            edits.push((slot.clone(), source[chunk.clone()].to_owned()));
+        }
+    }
+}
+
+fn apply(source: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    edits.sort_by_key(|(range, _)| range.start);
+    let mut result = String::with_capacity(source.len());
+    let mut position = 0;
+    for (range, text) in edits {
+        result.push_str(&source[position..range.start]);
+        result.push_str(&text);
+        position = range.end;
+    }
+    result.push_str(&source[position..]);
+    result
+}
+
+/// The `Entity` enum and the names of the structs of its variants.
+fn entity_structs(file: &syn::File) -> Result<(&syn::ItemEnum, Vec<String>), GeneratedError> {
+    let entity_enum = file
+        .items
+        .iter()
+        .find_map(|item| match item {
+            syn::Item::Enum(item) if item.ident == "Entity" => Some(item),
+            _ => None,
+        })
+        .ok_or(GeneratedError::NoEntityEnum)?;
+    let names = entity_enum
+        .variants
+        .iter()
+        .filter_map(
+            |variant| match variant.fields.iter().next().map(|field| &field.ty) {
+                Some(syn::Type::Path(path)) => path.path.segments.last(),
+                _ => None,
+            },
+        )
+        .map(|segment| segment.ident.to_string())
+        .collect();
+    Ok((entity_enum, names))
+}
+
+/// Sort the variants of the `Entity` enum and the fields of the entity structs.
+fn sort_members(source: &str) -> Result<String, GeneratedError> {
+    let file = syn::parse_file(source).map_err(GeneratedError::Syn)?;
+    let offsets = Offsets::new(source);
+    let (entity_enum, struct_names) = entity_structs(&file)?;
+    let mut edits = Vec::new();
+
+    let mut start = offsets.end(entity_enum.brace_token.span.open());
+    let mut variants = Vec::new();
+    for pair in entity_enum.variants.pairs() {
+        let end = offsets.end(pair.value().span());
+        variants.push((start..end, pair.value().ident.clone()));
+        start = pair.punct().map_or(end, |punct| offsets.end(punct.span));
+    }
+    reorder(source, variants, &mut edits);
+
+    for item in &file.items {
+        let syn::Item::Struct(item) = item else {
+            continue;
+        };
+        let syn::Fields::Named(fields) = &item.fields else {
+            continue;
+        };
+        if !struct_names.contains(&item.ident.to_string()) {
+            continue;
+        }
+        let mut start = offsets.end(fields.brace_token.span.open());
+        let mut elements = Vec::new();
+        for pair in fields.named.pairs() {
+            let end = offsets.end(pair.value().span());
+            elements.push((start..end, pair.value().ident.clone()));
+            start = pair.punct().map_or(end, |punct| offsets.end(punct.span));
+        }
+        reorder(source, elements, &mut edits);
+    }
+    Ok(apply(source, edits))
+}
+
+/// Sort the entity structs among the places they occupy in the file.
+fn sort_structs(source: &str) -> Result<String, GeneratedError> {
+    let file = syn::parse_file(source).map_err(GeneratedError::Syn)?;
+    let offsets = Offsets::new(source);
+    let (_, struct_names) = entity_structs(&file)?;
+    let mut elements = Vec::new();
+    let mut start = None;
+    for item in &file.items {
+        let span = item.span();
+        if let syn::Item::Struct(item) = item {
+            if struct_names.contains(&item.ident.to_string()) {
+                let start = start.unwrap_or_else(|| offsets.start(span));
+                elements.push((start..offsets.end(span), item.ident.clone()));
+            }
+        }
+        start = Some(offsets.end(span));
+    }
+    let mut edits = Vec::new();
+    reorder(source, elements, &mut edits);
+    Ok(apply(source, edits))
+}
+
+/// Sort an entities file the same way [`crate::EntityCollector`] sorts the generated code.
+///
+/// Only the entity structs, the variants of the `Entity` enum and the struct fields move,
+/// keeping their attributes and the comments in front of them.
+pub fn normalize(source: &str) -> Result<String, GeneratedError> {
+    sort_structs(&sort_members(source)?)
+}
@@ -0,0 +1,288 @@
+use crate::lump::{self, BspFormat, LumpError};
+
+/// The Source game lump, which addresses its sub lumps by file offset.
+const GAME_LUMP: usize = 35;
+
+#[derive(Debug)]
+pub enum PatchError {
+    UnknownFormat,
+    Lump(LumpError),
+    NoLumpPatches(BspFormat),
+}
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "not a bsp of a known format"),
+            PatchError::Lump(error) => write!(f, "{error}"),
+            PatchError::NoLumpPatches(format) => {
+                write!(f, "{format:?} maps don't load lump patches")
+            }
+        }
+    }
+}
+impl std::error::Error for PatchError {}
+
+struct Lump {
+    index: usize,
+    offset: usize,
+    length: usize,
+}
+
+fn entry(format: BspFormat, index: usize) -> usize {
+    format.lump_directory() + index * format.lump_entry_size()
+}
+
+fn directory(bsp: &[u8], format: BspFormat) -> Result<Vec<Lump>, PatchError> {
+    (0..format.lump_count())
+        .map(|index| {
+            let field = |offset: usize| {
+                let value = lump::read_i32(bsp, entry(format, index) + offset)
+                    .ok_or(PatchError::Lump(LumpError::TooShort))?;
+                usize::try_from(value).map_err(|_| PatchError::Lump(LumpError::OutOfBounds))
+            };
+            let lump = Lump {
+                index,
+                offset: field(0)?,
+                length: field(4)?,
+            };
+            if lump.offset.saturating_add(lump.length) > bsp.len() {
+                return Err(PatchError::Lump(LumpError::OutOfBounds));
+            }
+            Ok(lump)
+        })
+        .collect()
+}
+
+fn write_i32(bytes: &mut [u8], offset: usize, value: usize) {
+    bytes[offset..offset + 4].copy_from_slice(&(value as i32).to_le_bytes());
+}
+
+/// The entity lump as compilers store it, nul terminated.
+fn entity_data(entities: &str) -> Vec<u8> {
+    let mut data = entities.as_bytes().to_vec();
+    data.push(0);
+    data
+}
+
+/// Move the file offsets of the game lump directory along with the game lump.
+fn relocate_game_lump(game_lump: &mut [u8], old_offset: usize, new_offset: usize) {
+    let Some(count) = lump::read_i32(game_lump, 0) else {
+        return;
+    };
+    for index in 0..usize::try_from(count).unwrap_or(0) {
+        // id, flags, version, file offset, length
+        let field = 4 + index * 16 + 8;
+        let Some(offset) = lump::read_i32(game_lump, field) else {
+            return;
+        };
+        if let Some(relative) = (offset as usize)
+            .checked_sub(old_offset)
+            .filter(|_| offset > 0)
+        {
+            write_i32(game_lump, field, new_offset + relative);
+        }
+    }
+}
+
+/// A copy of the bsp with its entity lump replaced by `entities`.
+///
+/// The lumps are laid out again in their original order, so the file only grows or
+/// shrinks by the change of the entity lump, and the lump directory is updated to match.
+pub fn replace_entity_lump(bsp: &[u8], entities: &str) -> Result<Vec<u8>, PatchError> {
+    let format = BspFormat::detect(bsp).ok_or(PatchError::UnknownFormat)?;
+    let mut lumps = directory(bsp, format)?;
+    let mut header_size = entry(format, format.lump_count());
+    if format == BspFormat::Source {
+        // the map revision
+        header_size += 4;
+    }
+    if bsp.len() < header_size {
+        return Err(PatchError::Lump(LumpError::TooShort));
+    }
+
+    let entity_data = entity_data(entities);
+    let mut patched = bsp[..header_size].to_vec();
+    lumps.sort_by_key(|lump| lump.offset);
+    for lump in lumps {
+        let entry = entry(format, lump.index);
+        let data = if lump.index == lump::ENTITY_LUMP as usize {
+            &entity_data[..]
+        } else {
+            &bsp[lump.offset..lump.offset + lump.length]
+        };
+        if data.is_empty() {
+            write_i32(&mut patched, entry, header_size);
+            continue;
+        }
+        patched.resize(patched.len().next_multiple_of(4), 0);
+        let offset = patched.len();
+        patched.extend_from_slice(data);
+        write_i32(&mut patched, entry, offset);
+        write_i32(&mut patched, entry + 4, data.len());
+        if format == BspFormat::Source && lump.index == lump::ENTITY_LUMP as usize {
+            // the new lump is stored uncompressed
+            patched[entry + 12..entry + 16].fill(0);
+        }
+        if format == BspFormat::Source && lump.index == GAME_LUMP {
+            relocate_game_lump(&mut patched[offset..], lump.offset, offset);
+        }
+    }
+    Ok(patched)
+}
+
+/// A `.lmp` lump patch replacing the entity lump of a Source bsp.
+pub fn entity_lmp(bsp: &[u8], entities: &str) -> Result<Vec<u8>, PatchError> {
+    let format = BspFormat::detect(bsp).ok_or(PatchError::UnknownFormat)?;
+    if format != BspFormat::Source {
+        return Err(PatchError::NoLumpPatches(format));
+    }
+    let entry = entry(format, lump::ENTITY_LUMP as usize);
+    let field = |offset: usize| {
+        lump::read_i32(bsp, entry + offset).ok_or(PatchError::Lump(LumpError::TooShort))
+    };
+    let revision = lump::bsp_revision(bsp).ok_or(PatchError::Lump(LumpError::TooShort))?;
+    let data = entity_data(entities);
+    let mut lmp = Vec::new();
+    for value in [
+        // the data follows the header
+        lump::LMP_HEADER_SIZE as i32,
+        lump::ENTITY_LUMP,
+        field(8)?,
+        data.len() as i32,
+        revision,
+    ] {
+        lmp.extend_from_slice(&value.to_le_bytes());
+    }
+    lmp.extend_from_slice(&data);
+    Ok(lmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER_SIZE: usize = 8 + 64 * 16 + 4;
+    const REVISION: i32 = 7;
+
+    fn read(bytes: &[u8], offset: usize) -> usize {
+        lump::read_i32(bytes, offset).unwrap() as usize
+    }
+
+    fn source_entry(index: usize) -> usize {
+        entry(BspFormat::Source, index)
+    }
+
+    /// A Source bsp with entities, a plane lump and a game lump holding one static prop lump.
+    fn source_bsp(entities: &str) -> Vec<u8> {
+        let mut bsp = vec![0; HEADER_SIZE];
+        bsp[..4].copy_from_slice(b"VBSP");
+        bsp[4..8].copy_from_slice(&20i32.to_le_bytes());
+        bsp[HEADER_SIZE - 4..].copy_from_slice(&REVISION.to_le_bytes());
+
+        let lump = |bsp: &mut Vec<u8>, index: usize, data: &[u8]| {
+            let offset = bsp.len();
+            bsp.extend_from_slice(data);
+            bsp.resize(bsp.len().next_multiple_of(4), 0);
+            write_i32(bsp, source_entry(index), offset);
+            write_i32(bsp, source_entry(index) + 4, data.len());
+            offset
+        };
+        lump(&mut bsp, 0, &entity_data(entities));
+        lump(&mut bsp, 1, &[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let game_lump_offset = bsp.len();
+        let mut game_lump = Vec::new();
+        game_lump.extend_from_slice(&1i32.to_le_bytes());
+        game_lump.extend_from_slice(b"prps");
+        game_lump.extend_from_slice(&[0, 0, 10, 0]);
+        // the static props follow the game lump directory
+        game_lump.extend_from_slice(&((game_lump_offset + 20) as i32).to_le_bytes());
+        game_lump.extend_from_slice(&4i32.to_le_bytes());
+        game_lump.extend_from_slice(b"prop");
+        lump(&mut bsp, GAME_LUMP, &game_lump);
+        // lumps without data point at the end of the header
+        for index in (2..64).filter(|&index| index != GAME_LUMP) {
+            write_i32(&mut bsp, source_entry(index), HEADER_SIZE);
+        }
+        bsp
+    }
+
+    #[test]
+    fn replace_relocates_lumps_after_the_entities() {
+        let bsp = source_bsp("{\n\"classname\" \"worldspawn\"\n}\n");
+        let entities = "{\n\"classname\" \"worldspawn\"\n\"message\" \"Capture The Flag!\"\n}\n";
+        let patched = replace_entity_lump(&bsp, entities).unwrap();
+
+        assert_eq!(patched[..8], bsp[..8]);
+        assert_eq!(lump::bsp_revision(&patched), Some(REVISION));
+        assert_eq!(
+            lump::bsp_entity_text(&patched, BspFormat::Source).unwrap(),
+            entities
+        );
+
+        let planes = read(&patched, source_entry(1));
+        assert_eq!(planes % 4, 0);
+        assert_eq!(read(&patched, source_entry(1) + 4), 8);
+        assert_eq!(patched[planes..planes + 8], [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let game_lump = read(&patched, source_entry(GAME_LUMP));
+        assert_eq!(game_lump % 4, 0);
+        assert!(game_lump > read(&bsp, source_entry(GAME_LUMP)));
+        let props = read(&patched, game_lump + 12);
+        assert_eq!(props, game_lump + 20);
+        assert_eq!(&patched[props..props + 4], b"prop");
+
+        // a shorter lump moves everything back
+        let shrunk = replace_entity_lump(&patched, "").unwrap();
+        assert!(shrunk.len() < bsp.len());
+        assert_eq!(read(&shrunk, source_entry(0) + 4), 1);
+        let game_lump = read(&shrunk, source_entry(GAME_LUMP));
+        assert_eq!(&shrunk[read(&shrunk, game_lump + 12)..][..4], b"prop");
+    }
+
+    #[test]
+    fn replace_clears_compression() {
+        let mut bsp = source_bsp("{\n}\n");
+        write_i32(&mut bsp, source_entry(0) + 12, 100);
+        let patched = replace_entity_lump(&bsp, "{\n}\n").unwrap();
+        assert_eq!(read(&patched, source_entry(0) + 12), 0);
+    }
+
+    #[test]
+    fn entity_lmp_header() {
+        let bsp = source_bsp("{\n}\n");
+        let entities = "{\n\"classname\" \"Worldspawn\"\n}\n";
+        let lmp = entity_lmp(&bsp, entities).unwrap();
+
+        let fields: Vec<i32> = (0..5)
+            .map(|i| lump::read_i32(&lmp, i * 4).unwrap())
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                20,
+                lump::ENTITY_LUMP,
+                0,
+                entities.len() as i32 + 1,
+                REVISION
+            ]
+        );
+        assert_eq!(&lmp[20..], entity_data(entities));
+        let (header, text) = lump::read_entity_lmp(&lmp).unwrap();
+        assert_eq!((header.map_revision, text.as_str()), (REVISION, entities));
+    }
+
+    #[test]
+    fn only_source_maps_have_lump_patches() {
+        let mut goldsrc = 30i32.to_le_bytes().to_vec();
+        goldsrc.resize(4 + 15 * 8, 0);
+        assert!(matches!(
+            entity_lmp(&goldsrc, ""),
+            Err(PatchError::NoLumpPatches(BspFormat::GoldSrc))
+        ));
+        assert!(matches!(
+            replace_entity_lump(b"junk", ""),
+            Err(PatchError::UnknownFormat)
+        ));
+    }
+}
@@ -0,0 +1,138 @@
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Files done and bytes read so far, reported on stderr.
+struct Progress {
+    enabled: bool,
+    files: usize,
+    bytes: u64,
+    done_files: usize,
+    done_bytes: u64,
+    start: Instant,
+    last_report: Option<Instant>,
+}
+
+impl Progress {
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    fn new(files: usize, bytes: u64) -> Self {
+        Self {
+            // progress lines are only useful to a person watching
+            enabled: std::io::stderr().is_terminal(),
+            files,
+            bytes,
+            done_files: 0,
+            done_bytes: 0,
+            start: Instant::now(),
+            last_report: None,
+        }
+    }
+
+    fn advance(&mut self, bytes: u64) {
+        self.done_files += 1;
+        self.done_bytes += bytes;
+        let now = Instant::now();
+        let due = self
+            .last_report
+            .is_none_or(|last| Self::INTERVAL <= now - last);
+        if due || self.done_files == self.files {
+            self.last_report = Some(now);
+            self.report();
+        }
+    }
+
+    fn report(&self) {
+        if !self.enabled {
+            return;
+        }
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let eta = if 0 < self.done_bytes {
+            let remaining = (self.bytes - self.done_bytes) as f64 / self.done_bytes as f64;
+            format!("{:.0}s", elapsed * remaining)
+        } else {
+            "?".to_owned()
+        };
+        const MIB: f64 = 1024.0 * 1024.0;
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\rfiles {}/{} {:.1}/{:.1} MiB eta {eta}    ",
+            self.done_files,
+            self.files,
+            self.done_bytes as f64 / MIB,
+            self.bytes as f64 / MIB,
+        );
+        if self.done_files == self.files {
+            let _ = writeln!(stderr);
+        }
+    }
+
+    fn finish(&self) {
+        if self.enabled && self.done_files != self.files {
+            let _ = writeln!(std::io::stderr());
+        }
+    }
+}
+
+/// Read the files on `jobs` worker threads, handing each result to `on_result` as soon as it completes.
+///
+/// Workers take the next file from a shared queue, largest files first,
+/// so a huge file started late can't keep a single worker busy after the others finished.
+/// A panic while reading a file is returned as the `Err` of its result.
+/// Once `on_result` returns false no more files are started, the files already being read still complete.
+/// Returns false if the pipeline was stopped early.
+pub fn read_parallel<T: Send>(
+    paths: Vec<PathBuf>,
+    jobs: usize,
+    read: impl Fn(&Path) -> T + Sync,
+    mut on_result: impl FnMut(PathBuf, std::thread::Result<T>) -> bool,
+) -> bool {
+    // files that can't be inspected sort last, the read reports the actual error
+    let mut queue: Vec<(PathBuf, u64)> = paths
+        .into_iter()
+        .map(|path| {
+            let size = std::fs::metadata(&path).map_or(0, |meta| meta.len());
+            (path, size)
+        })
+        .collect();
+    queue.sort_by_key(|&(_, size)| std::cmp::Reverse(size));
+    let mut progress = Progress::new(queue.len(), queue.iter().map(|(_, size)| size).sum());
+
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let mut completed = true;
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..jobs.max(1).min(queue.len()) {
+            let sender = sender.clone();
+            let (queue, next, stop, read) = (&queue, &next, &stop, &read);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let Some((path, size)) = queue.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+                    let result =
+                        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| read(path)));
+                    if sender.send((path.clone(), *size, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // the receiver ends once every worker dropped its sender
+        drop(sender);
+
+        for (path, size, result) in receiver {
+            progress.advance(size);
+            if !on_result(path, result) && completed {
+                completed = false;
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+    progress.finish();
+    completed
+}
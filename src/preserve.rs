@@ -0,0 +1,275 @@
+use quote::ToTokens;
+use std::path::Path;
+use syn::punctuated::Punctuated;
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum PreserveError {
+    Io(std::io::Error),
+    Syn(syn::Error),
+}
+impl std::fmt::Display for PreserveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreserveError::Io(error) => write!(f, "Io: {error}"),
+            PreserveError::Syn(error) => write!(f, "Syn: {error}"),
+        }
+    }
+}
+impl std::error::Error for PreserveError {}
+
+/// Serde options the generated code sets, anything else in a serde attribute was written by hand.
+const GENERATED_SERDE_OPTIONS: &[&str] =
+    &["borrow", "default", "deserialize_with", "rename", "tag"];
+
+/// `codegen(keep)`, possibly wrapped in a `cfg_attr` so the file compiles without defining the attribute.
+fn is_keep_marker(meta: &syn::Meta) -> bool {
+    let syn::Meta::List(list) = meta else {
+        return false;
+    };
+    if list.path.is_ident("codegen") {
+        return list.tokens.to_string() == "keep";
+    }
+    if list.path.is_ident("cfg_attr") {
+        let Ok(metas) =
+            list.parse_args_with(Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+        else {
+            return false;
+        };
+        // the first meta is the predicate
+        return metas.iter().skip(1).any(is_keep_marker);
+    }
+    false
+}
+
+fn is_kept(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| is_keep_marker(&attr.meta))
+}
+
+/// Whether the attribute is owned by the generated code and gets replaced on every run.
+fn is_generated_attr(attr: &syn::Attribute, generated: &[syn::Attribute]) -> bool {
+    let tokens = attr.to_token_stream().to_string();
+    if generated
+        .iter()
+        .any(|generated| generated.to_token_stream().to_string() == tokens)
+    {
+        return true;
+    }
+    if !attr.path().is_ident("serde") {
+        return false;
+    }
+    let mut only_generated = true;
+    let parsed = attr.parse_nested_meta(|meta| {
+        if !GENERATED_SERDE_OPTIONS
+            .iter()
+            .any(|option| meta.path.is_ident(option))
+        {
+            only_generated = false;
+        }
+        if meta.input.peek(syn::Token![=]) {
+            meta.value()?.parse::<syn::Expr>()?;
+        }
+        Ok(())
+    });
+    parsed.is_ok() && only_generated
+}
+
+fn derive_paths(attr: &syn::Attribute) -> Vec<syn::Path> {
+    if !attr.path().is_ident("derive") {
+        return Vec::new();
+    }
+    attr.parse_args_with(Punctuated::<syn::Path, syn::Token![,]>::parse_terminated)
+        .map(|paths| paths.into_iter().collect())
+        .unwrap_or_default()
+}
+
+/// Remove the generated derives from a derive attribute, rustfmt merges hand-written derives into them.
+fn hand_written_derives(
+    attr: &syn::Attribute,
+    generated: &[syn::Attribute],
+) -> Option<syn::Attribute> {
+    let generated: Vec<String> = generated
+        .iter()
+        .flat_map(derive_paths)
+        .map(|path| path.to_token_stream().to_string())
+        .collect();
+    let paths: Vec<syn::Path> = derive_paths(attr)
+        .into_iter()
+        .filter(|path| !generated.contains(&path.to_token_stream().to_string()))
+        .collect();
+    (!paths.is_empty()).then(|| syn::parse_quote!(#[derive(#(#paths),*)]))
+}
+
+/// The generated attributes, with the hand-written ones of the existing element around them.
+fn merge_attrs(existing: &[syn::Attribute], generated: Vec<syn::Attribute>) -> Vec<syn::Attribute> {
+    let (docs, others): (Vec<_>, Vec<_>) = existing
+        .iter()
+        .filter(|attr| !is_generated_attr(attr, &generated))
+        .filter_map(|attr| {
+            if attr.path().is_ident("derive") {
+                hand_written_derives(attr, &generated)
+            } else {
+                Some(attr.clone())
+            }
+        })
+        .partition(|attr| attr.path().is_ident("doc"));
+    docs.into_iter().chain(generated).chain(others).collect()
+}
+
+/// Merge elements matched by name, kept existing elements replace the generated ones
+/// and kept elements the generated code no longer has are appended.
+fn merge_elements<T: Clone, K: PartialEq>(
+    existing: &[T],
+    generated: Vec<T>,
+    key: impl Fn(&T) -> K,
+    attrs: impl Fn(&T) -> &[syn::Attribute],
+    merge: impl Fn(&T, T) -> T,
+) -> Vec<T> {
+    let generated_keys: Vec<K> = generated.iter().map(&key).collect();
+    let mut merged: Vec<T> = generated
+        .into_iter()
+        .map(|generated| {
+            match existing
+                .iter()
+                .find(|existing| key(existing) == key(&generated))
+            {
+                Some(existing) if is_kept(attrs(existing)) => existing.clone(),
+                Some(existing) => merge(existing, generated),
+                None => generated,
+            }
+        })
+        .collect();
+    merged.extend(
+        existing
+            .iter()
+            .filter(|existing| is_kept(attrs(existing)) && !generated_keys.contains(&key(existing)))
+            .cloned(),
+    );
+    merged
+}
+
+fn merge_fields(existing: &syn::Fields, generated: syn::Fields) -> syn::Fields {
+    let (syn::Fields::Named(existing), syn::Fields::Named(mut generated)) =
+        (existing, generated.clone())
+    else {
+        return generated;
+    };
+    let existing: Vec<_> = existing.named.iter().cloned().collect();
+    let fields = merge_elements(
+        &existing,
+        generated.named.into_iter().collect(),
+        |field| field.ident.clone(),
+        |field| &field.attrs,
+        |existing, mut generated| {
+            generated.attrs = merge_attrs(&existing.attrs, generated.attrs);
+            generated
+        },
+    );
+    generated.named = fields.into_iter().collect();
+    syn::Fields::Named(generated)
+}
+
+fn merge_variants(
+    existing: &Punctuated<syn::Variant, syn::Token![,]>,
+    generated: Punctuated<syn::Variant, syn::Token![,]>,
+) -> Punctuated<syn::Variant, syn::Token![,]> {
+    let existing: Vec<_> = existing.iter().cloned().collect();
+    merge_elements(
+        &existing,
+        generated.into_iter().collect(),
+        |variant| variant.ident.clone(),
+        |variant| &variant.attrs,
+        |existing, mut generated| {
+            generated.attrs = merge_attrs(&existing.attrs, generated.attrs);
+            generated
+        },
+    )
+    .into_iter()
+    .collect()
+}
+
+/// Identifies the same item across both files.
+fn item_key(item: &syn::Item) -> String {
+    match item {
+        syn::Item::Struct(item) => format!("struct {}", item.ident),
+        syn::Item::Enum(item) => format!("enum {}", item.ident),
+        syn::Item::Fn(item) => format!("fn {}", item.sig.ident),
+        syn::Item::Type(item) => format!("type {}", item.ident),
+        syn::Item::Const(item) => format!("const {}", item.ident),
+        syn::Item::Impl(item) => {
+            let trait_ = item
+                .trait_
+                .as_ref()
+                .map(|(_, path, _)| path.to_token_stream().to_string());
+            format!(
+                "impl {} for {}",
+                trait_.unwrap_or_default(),
+                item.self_ty.to_token_stream()
+            )
+        }
+        _ => item.to_token_stream().to_string(),
+    }
+}
+
+fn item_attrs(item: &syn::Item) -> &[syn::Attribute] {
+    match item {
+        syn::Item::Struct(item) => &item.attrs,
+        syn::Item::Enum(item) => &item.attrs,
+        syn::Item::Fn(item) => &item.attrs,
+        syn::Item::Type(item) => &item.attrs,
+        syn::Item::Const(item) => &item.attrs,
+        syn::Item::Impl(item) => &item.attrs,
+        syn::Item::Use(item) => &item.attrs,
+        syn::Item::Mod(item) => &item.attrs,
+        syn::Item::Trait(item) => &item.attrs,
+        _ => &[],
+    }
+}
+
+fn merge_item(existing: &syn::Item, generated: syn::Item) -> syn::Item {
+    match (existing, generated) {
+        (syn::Item::Struct(existing), syn::Item::Struct(mut generated)) => {
+            generated.attrs = merge_attrs(&existing.attrs, generated.attrs);
+            generated.fields = merge_fields(&existing.fields, generated.fields);
+            syn::Item::Struct(generated)
+        }
+        (syn::Item::Enum(existing), syn::Item::Enum(mut generated)) => {
+            generated.attrs = merge_attrs(&existing.attrs, generated.attrs);
+            generated.variants = merge_variants(&existing.variants, generated.variants);
+            syn::Item::Enum(generated)
+        }
+        (_, generated) => generated,
+    }
+}
+
+/// Carry the hand-written edits of a previously generated file over to the newly generated one.
+///
+/// Items, fields and variants marked with `#[codegen(keep)]` are kept exactly as they are,
+/// including the ones the generated code no longer has.
+/// Everything else is replaced by the generated code,
+/// except attributes in a form the generated code never uses, like doc comments or extra derives.
+pub fn merge(existing: &syn::File, generated: syn::File) -> syn::File {
+    let items = merge_elements(
+        &existing.items,
+        generated.items,
+        item_key,
+        item_attrs,
+        merge_item,
+    );
+    syn::File {
+        shebang: existing.shebang.clone(),
+        attrs: existing.attrs.clone(),
+        items,
+    }
+}
+
+/// [`merge`] with the file at `path`, the generated file is returned as is if there is no file yet.
+pub fn merge_existing(path: &Path, generated: syn::File) -> Result<syn::File, PreserveError> {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(generated),
+        Err(error) => return Err(PreserveError::Io(error)),
+    };
+    let existing = syn::parse_file(&source).map_err(PreserveError::Syn)?;
+    Ok(merge(&existing, generated))
+}
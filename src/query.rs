@@ -0,0 +1,74 @@
+/// Match `text` against a pattern where `*` matches any run of characters and `?` any single character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // position of the last `*` and the text position it currently stands for
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // let the last `*` swallow one more character
+                Some((star, star_t)) => {
+                    backtrack = Some((star, star_t + 1));
+                    p = star + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// One condition an entity has to meet.
+#[derive(Debug, Clone)]
+pub enum Filter {
+    /// `key=PATTERN`, some key name matches the pattern.
+    Key(String),
+    /// `NAME=PATTERN`, some value of the key matches the pattern.
+    Value { key: String, pattern: String },
+}
+
+impl std::str::FromStr for Filter {
+    type Err = String;
+
+    fn from_str(filter: &str) -> Result<Self, Self::Err> {
+        let Some((key, pattern)) = filter.split_once('=') else {
+            return Err(format!("expected KEY=PATTERN or key=NAME, got {filter:?}"));
+        };
+        // the entity lump is lowercase
+        let (key, pattern) = (key.to_ascii_lowercase(), pattern.to_ascii_lowercase());
+        Ok(if key == "key" {
+            Filter::Key(pattern)
+        } else {
+            Filter::Value { key, pattern }
+        })
+    }
+}
+
+impl Filter {
+    fn matches(&self, entity: &vbsp::RawEntity) -> bool {
+        match self {
+            Filter::Key(pattern) => entity
+                .properties()
+                .any(|(name, _)| glob_match(pattern, name)),
+            Filter::Value { key, pattern } => entity
+                .properties()
+                .any(|(name, value)| name == key && glob_match(pattern, value)),
+        }
+    }
+}
+
+/// Whether the entity meets every filter.
+pub fn matches(filters: &[Filter], entity: &vbsp::RawEntity) -> bool {
+    filters.iter().all(|filter| filter.matches(entity))
+}
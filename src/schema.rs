@@ -0,0 +1,211 @@
+use crate::infer::{choose_type, TypeCounts};
+use crate::EntityPropertyType;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Everything known about the entity classes, independent of the code emitted for them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Schema {
+    /// Sorted by classname.
+    pub classes: Vec<ClassSchema>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassSchema {
+    pub classname: String,
+    /// Number of entities observed with this classname.
+    pub occurrences: usize,
+    /// The C++ class linked to the classname in the SDK.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sdk_class: Option<String>,
+    /// Sorted by name.
+    pub keys: Vec<KeySchema>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeySchema {
+    pub name: String,
+    pub ty: EntityPropertyType,
+    /// Not every entity of the class has this key.
+    pub optional: bool,
+    /// Number of values observed for this key.
+    pub count: usize,
+    /// How many of the observed values parse as each candidate type.
+    ///
+    /// Empty when the values are unknown, merging then falls back to [`EntityPropertyType::widen`].
+    #[serde(default, skip_serializing_if = "TypeCounts::is_empty")]
+    pub type_counts: TypeCounts,
+    pub provenance: Provenance,
+}
+
+/// Where the type of a key came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provenance {
+    /// Declared in the SDK data.
+    Sdk,
+    /// Inferred from the observed values.
+    Inferred,
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum SchemaError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// Both schemas declare a different sdk type for the same key.
+    Conflict {
+        class: String,
+        key: String,
+        types: [EntityPropertyType; 2],
+    },
+}
+impl std::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaError::Io(error) => write!(f, "Io: {error}"),
+            SchemaError::Json(error) => write!(f, "Json: {error}"),
+            SchemaError::Conflict {
+                class,
+                key,
+                types: [a, b],
+            } => write!(
+                f,
+                "Conflicting sdk types: class={class} key={key} types={a:?},{b:?}"
+            ),
+        }
+    }
+}
+impl std::error::Error for SchemaError {}
+
+impl Schema {
+    pub fn read(path: &Path) -> Result<Self, SchemaError> {
+        let json = std::fs::read_to_string(path).map_err(SchemaError::Io)?;
+        serde_json::from_str(&json).map_err(SchemaError::Json)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), SchemaError> {
+        let file = std::fs::File::create(path).map_err(SchemaError::Io)?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self).map_err(SchemaError::Json)
+    }
+
+    pub fn class(&self, classname: &str) -> Option<&ClassSchema> {
+        self.classes
+            .iter()
+            .find(|class| class.classname == classname)
+    }
+
+    /// Add the observations of another schema, as if both had been inferred in one run.
+    pub fn merge(&mut self, other: Schema) -> Result<(), SchemaError> {
+        for other_class in other.classes {
+            match self
+                .classes
+                .iter_mut()
+                .find(|class| class.classname == other_class.classname)
+            {
+                Some(class) => class.merge(other_class)?,
+                None => self.classes.push(other_class),
+            }
+        }
+        self.sort();
+        Ok(())
+    }
+
+    /// Sort classes and keys for consistency.
+    pub fn sort(&mut self) {
+        self.classes.sort_by(|a, b| a.classname.cmp(&b.classname));
+        for class in &mut self.classes {
+            class.keys.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+    }
+}
+
+impl ClassSchema {
+    fn merge(&mut self, other: ClassSchema) -> Result<(), SchemaError> {
+        // keys missing from either side are now missing from some entities
+        for key in &mut self.keys {
+            if other.occurrences > 0 && !other.keys.iter().any(|other| other.name == key.name) {
+                key.optional = true;
+            }
+        }
+        let occurrences = self.occurrences;
+        self.occurrences += other.occurrences;
+        if other.sdk_class.is_some() {
+            self.sdk_class = other.sdk_class;
+        }
+        for mut other_key in other.keys {
+            match self.keys.iter_mut().find(|key| key.name == other_key.name) {
+                Some(key) => {
+                    if key.provenance == Provenance::Sdk
+                        && other_key.provenance == Provenance::Sdk
+                        && key.ty != other_key.ty
+                    {
+                        return Err(SchemaError::Conflict {
+                            class: self.classname.clone(),
+                            key: key.name.clone(),
+                            types: [key.ty, other_key.ty],
+                        });
+                    }
+                    key.merge(other_key)
+                }
+                None => {
+                    other_key.optional |= occurrences > 0;
+                    self.keys.push(other_key)
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl KeySchema {
+    /// The values name other entities, so they are never a fixed list of choices.
+    pub fn names_entities(&self) -> bool {
+        matches!(self.name.as_str(), "targetname" | "target" | "parentname")
+            || self.ty == EntityPropertyType::EntityHandle
+    }
+
+    /// The type to widen from, bools that were always `0` or `1` widen like integers.
+    fn widening_type(&self) -> EntityPropertyType {
+        let numeric =
+            self.count > 0 && self.type_counts.get(&EntityPropertyType::U8) == Some(&self.count);
+        if self.ty == EntityPropertyType::Bool && numeric {
+            EntityPropertyType::U8
+        } else {
+            self.ty
+        }
+    }
+
+    fn merge(&mut self, other: KeySchema) {
+        let can_recount = !self.type_counts.is_empty() && !other.type_counts.is_empty();
+        let widened = if self.ty == other.ty {
+            self.ty
+        } else {
+            self.widening_type().widen(other.widening_type())
+        };
+        self.count += other.count;
+        self.optional |= other.optional;
+        for (ty, count) in other.type_counts {
+            *self.type_counts.entry(ty).or_insert(0) += count;
+        }
+        self.ty = match (self.provenance, other.provenance) {
+            // the sdk declares the type regardless of the observed values
+            (Provenance::Sdk, _) => self.ty,
+            (_, Provenance::Sdk) => {
+                self.provenance = Provenance::Sdk;
+                other.ty
+            }
+            (Provenance::Inferred, Provenance::Inferred) if can_recount => choose_type(
+                &self.name,
+                &self.type_counts,
+                self.count,
+                &"(values not kept)",
+            ),
+            (Provenance::Inferred, Provenance::Inferred) => widened,
+        };
+        if !can_recount {
+            // partial counts would make later merges choose wrong types
+            self.type_counts.clear();
+        }
+    }
+}
@@ -1,7 +1,9 @@
 use crate::EntityPropertyType;
 use serde::Deserialize;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::iter::once;
+use std::path::{Path, PathBuf};
 
 #[derive(Deserialize, Debug)]
 struct EntityClass<'a> {
@@ -22,32 +24,149 @@ struct FoundType<'a> {
     ty: &'a str,
 }
 
-pub struct SdkData<'a> {
-    classes: Vec<EntityClass<'a>>,
-    inherits: Vec<Inherit<'a>>,
-    types: Vec<FoundType<'a>>,
+/// A [`FoundType`] with the type string resolved.
+struct SdkType<'a> {
+    class: &'a str,
+    name: &'a str,
+    ty: EntityPropertyType,
 }
 
-impl SdkData<'static> {
-    pub fn new() -> Self {
-        Self::load(
-            include_str!("../data/classes.json"),
-            include_str!("../data/inherits.json"),
-            include_str!("../data/types.json"),
-        )
+/// How to treat type strings that have no matching [`EntityPropertyType`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownTypePolicy {
+    /// Fail loading the sdk data.
+    Error,
+    /// Report the type and treat the key as a string.
+    Str,
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum SdkDataError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    EmptyLayer(PathBuf),
+    Json {
+        file: String,
+        error: serde_json::Error,
+    },
+    UnknownType {
+        class: String,
+        key: String,
+        ty: String,
+    },
+}
+impl std::fmt::Display for SdkDataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SdkDataError::Io { path, error } => write!(f, "Io: {}: {error}", path.display()),
+            SdkDataError::EmptyLayer(path) => {
+                write!(f, "No sdk data json files in {}", path.display())
+            }
+            SdkDataError::Json { file, error } => write!(f, "Json: {file}: {error}"),
+            SdkDataError::UnknownType { class, key, ty } => {
+                write!(f, "Unknown type: class={class} key={key} type={ty}")
+            }
+        }
     }
 }
+impl std::error::Error for SdkDataError {}
+
+/// The json files of one sdk data set, as written by sdk-parser.
+///
+/// Files missing from a layer leave the data of the layers below untouched.
+pub struct SdkDataLayer {
+    name: String,
+    classes: Option<Cow<'static, str>>,
+    inherits: Option<Cow<'static, str>>,
+    types: Option<Cow<'static, str>>,
+}
+
+impl SdkDataLayer {
+    /// The data extracted from the Source SDK 2013, included in the binary.
+    pub fn bundled() -> Self {
+        Self {
+            name: "bundled".to_owned(),
+            classes: Some(Cow::Borrowed(include_str!("../data/classes.json"))),
+            inherits: Some(Cow::Borrowed(include_str!("../data/inherits.json"))),
+            types: Some(Cow::Borrowed(include_str!("../data/types.json"))),
+        }
+    }
+
+    /// Read `classes.json`, `inherits.json` and `types.json` from a directory.
+    pub fn read_dir(dir: &Path) -> Result<Self, SdkDataError> {
+        let read = |file| {
+            let path = dir.join(file);
+            match std::fs::read_to_string(&path) {
+                Ok(json) => Ok(Some(Cow::Owned(json))),
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(error) => Err(SdkDataError::Io { path, error }),
+            }
+        };
+        let layer = Self {
+            name: dir.display().to_string(),
+            classes: read("classes.json")?,
+            inherits: read("inherits.json")?,
+            types: read("types.json")?,
+        };
+        if layer.classes.is_none() && layer.inherits.is_none() && layer.types.is_none() {
+            return Err(SdkDataError::EmptyLayer(dir.to_owned()));
+        }
+        Ok(layer)
+    }
+
+    fn parse<'a, T: Deserialize<'a>>(
+        &self,
+        file: &str,
+        json: &'a Option<Cow<'static, str>>,
+    ) -> Result<Vec<T>, SdkDataError> {
+        match json {
+            Some(json) => serde_json::from_str(json).map_err(|error| SdkDataError::Json {
+                file: format!("{}/{file}", self.name),
+                error,
+            }),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+pub struct SdkData<'a> {
+    classes: Vec<EntityClass<'a>>,
+    inherits: Vec<Inherit<'a>>,
+    types: Vec<SdkType<'a>>,
+}
 
 impl<'a> SdkData<'a> {
-    fn load(classes_json: &'a str, inherits_json: &'a str, types_json: &'a str) -> Self {
-        SdkData {
-            classes: serde_json::from_str(classes_json).unwrap(),
-            inherits: serde_json::from_str(inherits_json).unwrap(),
-            types: serde_json::from_str(types_json).unwrap(),
+    /// Load the layers in order, entries of later layers replace the matching entries of earlier ones.
+    pub fn load(
+        layers: &'a [SdkDataLayer],
+        policy: UnknownTypePolicy,
+    ) -> Result<Self, SdkDataError> {
+        let mut classes = Vec::new();
+        let mut inherits = Vec::new();
+        let mut types = Vec::new();
+        for layer in layers {
+            let layer_classes: Vec<EntityClass> = layer.parse("classes.json", &layer.classes)?;
+            let layer_inherits: Vec<Inherit> = layer.parse("inherits.json", &layer.inherits)?;
+            let layer_types: Vec<FoundType> = layer.parse("types.json", &layer.types)?;
+            override_entries(&mut classes, layer_classes, |class| class.entity);
+            override_entries(&mut inherits, layer_inherits, |inherit| inherit.name);
+            let layer_types = layer_types
+                .into_iter()
+                .map(|found| resolve_type(found, policy))
+                .collect::<Result<_, _>>()?;
+            override_entries(&mut types, layer_types, |ty| (ty.class, ty.name));
         }
+        Ok(SdkData {
+            classes,
+            inherits,
+            types,
+        })
     }
 
-    fn class_for_entity(&self, entity: &str) -> Option<&'a str> {
+    pub fn class_for_entity(&self, entity: &str) -> Option<&'a str> {
         self.classes
             .iter()
             .find(|class| class.entity == entity)
@@ -62,7 +181,20 @@ impl<'a> SdkData<'a> {
             .unwrap_or_default()
     }
 
-    fn types_for_class(&'a self, class: &'a str) -> impl Iterator<Item = &'a FoundType<'a>> {
+    /// The class followed by its primary base classes, up to the root.
+    pub fn inheritance_chain(&'a self, class: &'a str) -> Vec<&'a str> {
+        let mut chain = vec![class];
+        while let Some(&base) = self.inherits_for_class(chain.last().unwrap()).first() {
+            // layered data may form a cycle
+            if chain.contains(&base) {
+                break;
+            }
+            chain.push(base);
+        }
+        chain
+    }
+
+    fn types_for_class(&'a self, class: &'a str) -> impl Iterator<Item = &'a SdkType<'a>> {
         self.types.iter().filter(move |types| types.class == class)
     }
 
@@ -74,7 +206,7 @@ impl<'a> SdkData<'a> {
         once(class)
             .chain(inherits.iter().copied())
             .flat_map(|class| self.types_for_class(class))
-            .map(|ty| (ty.name, map_sdk_type(ty.ty)))
+            .map(|ty| (ty.name, ty.ty))
             .collect()
     }
 
@@ -88,7 +220,7 @@ impl<'a> SdkData<'a> {
                 let properties = once(class)
                     .chain(inherits.iter().copied())
                     .flat_map(|class| self.types_for_class(class))
-                    .map(|ty| (ty.name, map_sdk_type(ty.ty)))
+                    .map(|ty| (ty.name, ty.ty))
                     .collect();
                 (entity, properties)
             })
@@ -96,15 +228,78 @@ impl<'a> SdkData<'a> {
     }
 }
 
-fn map_sdk_type(ty: &str) -> EntityPropertyType {
-    match ty {
+/// Replace entries with the same key, append the rest.
+///
+/// Within one layer the first entry for a key wins, like a lookup with `find` would.
+fn override_entries<T, K: Eq + std::hash::Hash>(
+    entries: &mut Vec<T>,
+    overrides: Vec<T>,
+    key: impl Fn(&T) -> K,
+) {
+    let mut index: HashMap<K, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (key(entry), i))
+        .collect();
+    let mut overridden = std::collections::HashSet::new();
+    for entry in overrides {
+        let entry_key = key(&entry);
+        match index.get(&entry_key) {
+            Some(&i) => {
+                if overridden.insert(i) {
+                    entries[i] = entry;
+                }
+            }
+            None => {
+                overridden.insert(entries.len());
+                index.insert(entry_key, entries.len());
+                entries.push(entry);
+            }
+        }
+    }
+}
+
+fn resolve_type(found: FoundType, policy: UnknownTypePolicy) -> Result<SdkType, SdkDataError> {
+    let ty = match (map_sdk_type(found.ty), policy) {
+        (Some(ty), _) => ty,
+        (None, UnknownTypePolicy::Str) => {
+            println!(
+                "unknown sdk type, using string: class={} key={} type={}",
+                found.class, found.name, found.ty
+            );
+            EntityPropertyType::Str
+        }
+        (None, UnknownTypePolicy::Error) => {
+            return Err(SdkDataError::UnknownType {
+                class: found.class.to_owned(),
+                key: found.name.to_owned(),
+                ty: found.ty.to_owned(),
+            })
+        }
+    };
+    Ok(SdkType {
+        class: found.class,
+        name: found.name,
+        ty,
+    })
+}
+
+fn map_sdk_type(ty: &str) -> Option<EntityPropertyType> {
+    Some(match ty {
         "color" => EntityPropertyType::Color,
         "vector" => EntityPropertyType::Vector,
         "string" => EntityPropertyType::Str,
         "f32" => EntityPropertyType::F32,
+        "i8" => EntityPropertyType::I8,
+        "i16" => EntityPropertyType::I16,
         "i32" => EntityPropertyType::I32,
+        "i64" => EntityPropertyType::I64,
+        "vector2d" => EntityPropertyType::Vector2D,
+        "model" => EntityPropertyType::ModelName,
+        "sound" => EntityPropertyType::SoundName,
+        "ehandle" => EntityPropertyType::EntityHandle,
         "bool" => EntityPropertyType::Bool,
         "angles" => EntityPropertyType::Angles,
-        _ => todo!(),
-    }
+        _ => return None,
+    })
 }
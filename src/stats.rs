@@ -0,0 +1,129 @@
+use crate::infer::Observations;
+use crate::schema::Schema;
+use crate::EntityPropertyType;
+use serde::Serialize;
+use std::io::Write;
+
+#[derive(Debug, Serialize)]
+pub struct ClassStats {
+    pub classname: String,
+    pub instances: usize,
+    /// Number of maps with at least one instance.
+    pub maps: usize,
+    pub keys: Vec<KeyStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyStats {
+    pub name: String,
+    /// The type chosen for the generated code.
+    pub ty: EntityPropertyType,
+    /// Fraction of the instances with this key.
+    pub presence: f64,
+    pub distinct: usize,
+    /// The most common values, most common first.
+    pub top: Vec<ValueCount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: usize,
+}
+
+/// Statistics of every class in the schema, from observations collected [`Observations::with_value_stats`].
+pub fn class_stats(observations: &Observations, schema: &Schema, top: usize) -> Vec<ClassStats> {
+    let mut stats = Vec::new();
+    for class in &schema.classes {
+        let Some(collector) = observations.classes.get(&class.classname) else {
+            continue;
+        };
+        let mut keys = Vec::new();
+        for key in &class.keys {
+            let Some((key_collector, values)) =
+                collector.keys.get(&key.name).and_then(|key_collector| {
+                    Some((key_collector, key_collector.value_stats.as_ref()?))
+                })
+            else {
+                continue;
+            };
+            let mut histogram: Vec<_> = values.histogram.iter().collect();
+            histogram.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+            keys.push(KeyStats {
+                name: key.name.clone(),
+                ty: key.ty,
+                presence: key_collector.entities as f64 / collector.occurrences as f64,
+                distinct: values.distinct(),
+                top: histogram
+                    .into_iter()
+                    .take(top)
+                    .map(|(value, &count)| ValueCount {
+                        value: value.clone(),
+                        count,
+                    })
+                    .collect(),
+                min: values.min,
+                max: values.max,
+            });
+        }
+        stats.push(ClassStats {
+            classname: class.classname.clone(),
+            instances: collector.occurrences,
+            maps: collector.maps,
+            keys,
+        });
+    }
+    stats
+}
+
+/// Print one table of keys per class.
+pub fn write_table(out: &mut dyn Write, stats: &[ClassStats]) -> std::io::Result<()> {
+    const HEADER: [&str; 7] = ["key", "type", "present", "distinct", "min", "max", "top"];
+    for class in stats {
+        writeln!(
+            out,
+            "{} instances={} maps={}",
+            class.classname, class.instances, class.maps
+        )?;
+        let number = |number: Option<f64>| number.map(|n| n.to_string()).unwrap_or_default();
+        let rows: Vec<[String; 7]> = class
+            .keys
+            .iter()
+            .map(|key| {
+                let top: Vec<_> = key
+                    .top
+                    .iter()
+                    .map(|value| format!("{:?}x{}", value.value, value.count))
+                    .collect();
+                [
+                    key.name.clone(),
+                    format!("{:?}", key.ty),
+                    format!("{:.1}%", key.presence * 100.0),
+                    key.distinct.to_string(),
+                    number(key.min),
+                    number(key.max),
+                    top.join(" "),
+                ]
+            })
+            .collect();
+        let mut widths = HEADER.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        let header = HEADER.map(str::to_owned);
+        for row in std::iter::once(&header).chain(&rows) {
+            let mut line = String::from(" ");
+            for (cell, width) in row.iter().zip(widths) {
+                line.push_str(&format!(" {cell:width$}"));
+            }
+            writeln!(out, "{}", line.trim_end())?;
+        }
+    }
+    Ok(())
+}
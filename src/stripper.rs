@@ -0,0 +1,375 @@
+use crate::lump;
+use crate::vmf::{KeyValuesError, Token, Tokenizer};
+use regex::{Regex, RegexBuilder};
+
+#[derive(Debug)]
+pub enum StripperError {
+    Syntax(KeyValuesError),
+    Regex { line: usize, error: regex::Error },
+}
+impl std::fmt::Display for StripperError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StripperError::Syntax(error) => write!(f, "{error}"),
+            StripperError::Regex { line, error } => write!(f, "line {line}: {error}"),
+        }
+    }
+}
+impl std::error::Error for StripperError {}
+impl From<KeyValuesError> for StripperError {
+    fn from(error: KeyValuesError) -> Self {
+        StripperError::Syntax(error)
+    }
+}
+
+type KeyValues = Vec<(String, String)>;
+
+/// A value to match, `/regex/` or an exact value, both ignoring case like the engine.
+enum Pattern {
+    Exact(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Pattern::Exact(exact) => exact.eq_ignore_ascii_case(value),
+            Pattern::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+struct Match {
+    key: String,
+    pattern: Pattern,
+}
+
+impl Match {
+    fn matches(&self, entity: &KeyValues) -> bool {
+        entity
+            .iter()
+            .any(|(key, value)| key.eq_ignore_ascii_case(&self.key) && self.pattern.matches(value))
+    }
+}
+
+enum Rule {
+    Filter(Vec<Match>),
+    Add(KeyValues),
+    Modify {
+        matches: Vec<Match>,
+        replace: KeyValues,
+        delete: Vec<Match>,
+        insert: KeyValues,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Section {
+    Filter,
+    Add,
+    Modify,
+}
+
+/// A Stripper:Source map config, the `filter:`, `add:` and `modify:` blocks in file order.
+pub struct Config {
+    rules: Vec<Rule>,
+}
+
+/// The keyvalues of a block up to its `}`, with the line of each value.
+fn keyvalues<'a>(
+    tokenizer: &mut Tokenizer<'a>,
+) -> Result<Vec<(&'a str, &'a str, usize)>, KeyValuesError> {
+    let mut keyvalues = Vec::new();
+    loop {
+        let key = match tokenizer.next()? {
+            Some(Token::Text(key)) => key,
+            Some(Token::Close) => return Ok(keyvalues),
+            Some(Token::Open) => return Err(tokenizer.error("unexpected {")),
+            None => return Err(tokenizer.error("missing }")),
+        };
+        match tokenizer.next()? {
+            Some(Token::Text(value)) => keyvalues.push((key, value, tokenizer.line())),
+            _ => return Err(tokenizer.error("missing value")),
+        }
+    }
+}
+
+fn open(tokenizer: &mut Tokenizer) -> Result<(), KeyValuesError> {
+    match tokenizer.next()? {
+        Some(Token::Open) => Ok(()),
+        _ => Err(tokenizer.error("expected {")),
+    }
+}
+
+fn owned(keyvalues: Vec<(&str, &str, usize)>) -> KeyValues {
+    keyvalues
+        .into_iter()
+        .map(|(key, value, _)| (key.to_owned(), value.to_owned()))
+        .collect()
+}
+
+fn matches(keyvalues: Vec<(&str, &str, usize)>) -> Result<Vec<Match>, StripperError> {
+    keyvalues
+        .into_iter()
+        .map(|(key, value, line)| {
+            let pattern = match value.strip_prefix('/').and_then(|v| v.strip_suffix('/')) {
+                Some(regex) => Pattern::Regex(
+                    RegexBuilder::new(regex)
+                        .case_insensitive(true)
+                        .build()
+                        .map_err(|error| StripperError::Regex { line, error })?,
+                ),
+                None => Pattern::Exact(value.to_owned()),
+            };
+            Ok(Match {
+                key: key.to_owned(),
+                pattern,
+            })
+        })
+        .collect()
+}
+
+fn modify(tokenizer: &mut Tokenizer) -> Result<Rule, StripperError> {
+    let (mut matched, mut replace, mut delete, mut insert) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    loop {
+        let label = match tokenizer.next()? {
+            Some(Token::Text(label)) => label.trim_end_matches(':').to_ascii_lowercase(),
+            Some(Token::Close) => break,
+            _ => {
+                return Err(tokenizer
+                    .error("expected match:, replace:, delete: or insert:")
+                    .into())
+            }
+        };
+        open(tokenizer)?;
+        let block = keyvalues(tokenizer)?;
+        match label.as_str() {
+            "match" => matched.extend(matches(block)?),
+            "replace" => replace.extend(owned(block)),
+            "delete" => delete.extend(matches(block)?),
+            "insert" => insert.extend(owned(block)),
+            _ => {
+                return Err(tokenizer
+                    .error("expected match:, replace:, delete: or insert:")
+                    .into())
+            }
+        }
+    }
+    Ok(Rule::Modify {
+        matches: matched,
+        replace,
+        delete,
+        insert,
+    })
+}
+
+impl Config {
+    pub fn parse(source: &str) -> Result<Self, StripperError> {
+        // blank out `;` and `#` comment lines, keeping the line numbers
+        let source: String = source
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim_start();
+                if trimmed.starts_with(';') || trimmed.starts_with('#') {
+                    ""
+                } else {
+                    line
+                }
+            })
+            .flat_map(|line| [line, "\n"])
+            .collect();
+        let mut tokenizer = Tokenizer::new(&source);
+        let mut section = None;
+        let mut rules = Vec::new();
+        loop {
+            match tokenizer.next()? {
+                None => break,
+                Some(Token::Text(label)) => {
+                    section = Some(
+                        match label.trim_end_matches(':').to_ascii_lowercase().as_str() {
+                            "filter" | "remove" => Section::Filter,
+                            "add" => Section::Add,
+                            "modify" => Section::Modify,
+                            _ => return Err(tokenizer.error("unknown section").into()),
+                        },
+                    );
+                }
+                Some(Token::Open) => {
+                    let rule = match section {
+                        None => return Err(tokenizer.error("block outside of a section").into()),
+                        Some(Section::Filter) => {
+                            let block = keyvalues(&mut tokenizer)?;
+                            Rule::Filter(matches(block)?)
+                        }
+                        Some(Section::Add) => Rule::Add(owned(keyvalues(&mut tokenizer)?)),
+                        Some(Section::Modify) => modify(&mut tokenizer)?,
+                    };
+                    rules.push(rule);
+                }
+                Some(Token::Close) => return Err(tokenizer.error("unexpected }").into()),
+            }
+        }
+        Ok(Config { rules })
+    }
+
+    /// The entity lump after running every rule over it.
+    pub fn apply(&self, lump: &str) -> String {
+        let mut entities: Vec<KeyValues> = vbsp::Entities {
+            entities: lump.to_owned(),
+        }
+        .iter()
+        .map(|entity| {
+            entity
+                .properties()
+                .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                .collect()
+        })
+        .collect();
+        let all = |matches: &[Match], entity: &KeyValues| {
+            matches.iter().all(|matched| matched.matches(entity))
+        };
+        for rule in &self.rules {
+            match rule {
+                Rule::Filter(matches) => entities.retain(|entity| !all(matches, entity)),
+                Rule::Add(keyvalues) => entities.push(keyvalues.clone()),
+                Rule::Modify {
+                    matches,
+                    replace,
+                    delete,
+                    insert,
+                } => {
+                    for entity in entities.iter_mut().filter(|entity| all(matches, entity)) {
+                        for (key, value) in entity.iter_mut() {
+                            if let Some((_, new)) = replace
+                                .iter()
+                                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                            {
+                                *value = new.clone();
+                            }
+                        }
+                        entity.retain(|(key, value)| {
+                            !delete.iter().any(|deleted| {
+                                deleted.key.eq_ignore_ascii_case(key)
+                                    && deleted.pattern.matches(value)
+                            })
+                        });
+                        entity.extend(insert.iter().cloned());
+                    }
+                }
+            }
+        }
+
+        lump::format_entities(entities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LUMP: &str = r#"{
+"classname" "worldspawn"
+}
+{
+"classname" "prop_dynamic"
+"targetname" "crate_1"
+"model" "models/crate.mdl"
+}
+{
+"classname" "prop_dynamic"
+"targetname" "barrel"
+"rendercolor" "255 0 0"
+}
+{
+"classname" "func_door"
+"speed" "100"
+}
+"#;
+
+    #[test]
+    fn apply_rules_in_order() {
+        let config = Config::parse(
+            r#"; drop the crates
+filter:
+{
+    "classname" "prop_dynamic"
+    "targetname" "/^CRATE_\d+$/"
+}
+add:
+{
+    "classname" "Info_Target"
+    "targetname" "Added"
+}
+# doors are slower
+modify:
+{
+    match:
+    {
+        "classname" "func_door"
+    }
+    replace:
+    {
+        "speed" "50"
+    }
+    insert:
+    {
+        "wait" "-1"
+    }
+}
+modify:
+{
+    match:
+    {
+        "targetname" "barrel"
+    }
+    delete:
+    {
+        "rendercolor" "/255/"
+    }
+}
+"#,
+        )
+        .unwrap();
+        let lump = config.apply(LUMP);
+        assert_eq!(
+            lump,
+            r#"{
+"classname" "worldspawn"
+}
+{
+"classname" "prop_dynamic"
+"targetname" "barrel"
+}
+{
+"classname" "func_door"
+"speed" "50"
+"wait" "-1"
+}
+{
+"classname" "Info_Target"
+"targetname" "Added"
+}
+"#
+        );
+    }
+
+    #[test]
+    fn parse_errors_have_lines() {
+        let Err(StripperError::Syntax(error)) = Config::parse("; comment\nfilter:\n{\n\"a\"\n}")
+        else {
+            panic!("expected a syntax error");
+        };
+        assert_eq!((error.line, error.message), (5, "missing value"));
+        let Err(StripperError::Regex { line, .. }) =
+            Config::parse("filter:\n{\n\"targetname\" \"/(/\"\n}")
+        else {
+            panic!("expected a regex error");
+        };
+        assert_eq!(line, 3);
+        assert!(matches!(
+            Config::parse("keep:\n{\n}"),
+            Err(StripperError::Syntax(_))
+        ));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::jsonschema::value_format;
+use crate::schema::{ClassSchema, Schema};
+use crate::EntityPropertyType;
+use std::collections::BTreeSet;
+
+/// The TypeScript type of a value, a template literal type for the types with a fixed format.
+fn ts_type(ty: EntityPropertyType) -> &'static str {
+    value_format(ty).map_or("string", |format| format.name)
+}
+
+fn property_name(name: &str) -> String {
+    let identifier = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if identifier {
+        name.to_owned()
+    } else {
+        format!("{name:?}")
+    }
+}
+
+/// The interface name, the same as the generated struct.
+fn interface_name(class: &ClassSchema) -> String {
+    heck::ToUpperCamelCase::to_upper_camel_case(class.classname.as_str())
+}
+
+fn interface(class: &ClassSchema) -> String {
+    let mut interface = format!(
+        "export interface {} {{\n  classname: {:?};\n",
+        interface_name(class),
+        class.classname
+    );
+    for key in &class.keys {
+        let optional = if key.optional { "?" } else { "" };
+        interface.push_str(&format!(
+            "  {}{optional}: {};\n",
+            property_name(&key.name),
+            ts_type(key.ty)
+        ));
+    }
+    interface.push('}');
+    interface
+}
+
+/// A `.d.ts` with an interface per class, named like the struct [`crate::EntityCollector::codegen`]
+/// generates with the same keys and optionality, and `Entity` as their union discriminated on `classname`.
+///
+/// An entity is the object of the pairs `dump --format json --lowercase` writes, the values
+/// as written in the lump with the formats of [`value_format`].
+pub fn typescript(schema: &Schema) -> String {
+    let mut classes: Vec<&ClassSchema> = schema.classes.iter().collect();
+    // the order of the generated structs
+    classes.sort_by_key(|class| interface_name(class));
+
+    let used_types: BTreeSet<EntityPropertyType> = classes
+        .iter()
+        .flat_map(|class| class.keys.iter().map(|key| key.ty))
+        .collect();
+    let mut declarations: Vec<String> = used_types
+        .into_iter()
+        .filter_map(value_format)
+        .map(|format| format!("export type {} = {};", format.name, format.typescript))
+        .collect();
+
+    let mut entity = String::from("export type Entity =");
+    for class in &classes {
+        entity.push_str(&format!("\n  | {}", interface_name(class)));
+    }
+    if classes.is_empty() {
+        entity.push_str(" never");
+    }
+    entity.push(';');
+    declarations.push(entity);
+    declarations.extend(classes.into_iter().map(interface));
+
+    let mut file = String::from(
+        "// generated by vbsp-entities-codegen\n\
+         // an entity is Object.fromEntries() of its pairs from `dump --format json --lowercase`\n",
+    );
+    for declaration in declarations {
+        file.push('\n');
+        file.push_str(&declaration);
+        file.push('\n');
+    }
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{KeySchema, Provenance};
+
+    fn key(name: &str, ty: EntityPropertyType, optional: bool) -> KeySchema {
+        KeySchema {
+            name: name.to_owned(),
+            ty,
+            optional,
+            count: 1,
+            type_counts: Default::default(),
+            provenance: Provenance::Inferred,
+        }
+    }
+
+    #[test]
+    fn interfaces_match_the_generated_structs() {
+        let schema = Schema {
+            classes: vec![
+                ClassSchema {
+                    classname: "trigger_multiple".to_owned(),
+                    occurrences: 2,
+                    sdk_class: None,
+                    keys: vec![
+                        key("model", EntityPropertyType::BrushModel, false),
+                        key("startdisabled", EntityPropertyType::Bool, true),
+                        key("wait", EntityPropertyType::F32, true),
+                    ],
+                },
+                ClassSchema {
+                    classname: "info_target".to_owned(),
+                    occurrences: 1,
+                    sdk_class: None,
+                    keys: vec![key("$x-offset", EntityPropertyType::Str, false)],
+                },
+            ],
+        };
+        assert_eq!(
+            typescript(&schema),
+            r#"// generated by vbsp-entities-codegen
+// an entity is Object.fromEntries() of its pairs from `dump --format json --lowercase`
+
+export type Bool = "0" | "1" | "no" | "yes";
+
+export type F32 = `${number}`;
+
+export type BrushModelRef = `*${number}`;
+
+export type Entity =
+  | InfoTarget
+  | TriggerMultiple;
+
+export interface InfoTarget {
+  classname: "info_target";
+  "$x-offset": string;
+}
+
+export interface TriggerMultiple {
+  classname: "trigger_multiple";
+  model: BrushModelRef;
+  startdisabled?: Bool;
+  wait?: F32;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn no_classes() {
+        assert!(typescript(&Schema::default()).contains("export type Entity = never;"));
+    }
+}
@@ -0,0 +1,340 @@
+/// A syntax error in a KeyValues file.
+#[derive(Debug)]
+pub struct KeyValuesError {
+    pub line: usize,
+    pub message: &'static str,
+}
+impl std::fmt::Display for KeyValuesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+impl std::error::Error for KeyValuesError {}
+
+pub enum Token<'a> {
+    Text(&'a str),
+    Open,
+    Close,
+}
+
+pub struct Tokenizer<'a> {
+    source: &'a str,
+    position: usize,
+    line: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Tokenizer {
+            source,
+            position: 0,
+            line: 1,
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn error(&self, message: &'static str) -> KeyValuesError {
+        KeyValuesError {
+            line: self.line,
+            message,
+        }
+    }
+
+    pub fn next(&mut self) -> Result<Option<Token<'a>>, KeyValuesError> {
+        loop {
+            let rest = &self.source[self.position..];
+            let Some(c) = rest.chars().next() else {
+                return Ok(None);
+            };
+            if c == '\n' {
+                self.line += 1;
+            }
+            if c.is_whitespace() {
+                self.position += c.len_utf8();
+            } else if rest.starts_with("//") {
+                self.position += rest.find('\n').unwrap_or(rest.len());
+            } else {
+                break;
+            }
+        }
+        let rest = &self.source[self.position..];
+        let token = match rest.as_bytes()[0] {
+            b'{' => {
+                self.position += 1;
+                Token::Open
+            }
+            b'}' => {
+                self.position += 1;
+                Token::Close
+            }
+            b'"' => {
+                let end = rest[1..]
+                    .find('"')
+                    .ok_or_else(|| self.error("unterminated string"))?;
+                let text = &rest[1..end + 1];
+                self.line += text.matches('\n').count();
+                self.position += end + 2;
+                Token::Text(text)
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || matches!(c, '{' | '}' | '"'))
+                    .unwrap_or(rest.len());
+                self.position += end;
+                Token::Text(&rest[..end])
+            }
+        };
+        Ok(Some(token))
+    }
+}
+
+/// A named block of keyvalues and nested blocks.
+#[derive(Debug, Default)]
+pub struct Block<'a> {
+    pub name: &'a str,
+    pub properties: Vec<(&'a str, &'a str)>,
+    pub children: Vec<Block<'a>>,
+}
+
+fn parse_members<'a>(
+    tokenizer: &mut Tokenizer<'a>,
+    block: &mut Block<'a>,
+    nested: bool,
+) -> Result<(), KeyValuesError> {
+    loop {
+        let key = match tokenizer.next()? {
+            Some(Token::Text(key)) => key,
+            Some(Token::Close) if nested => return Ok(()),
+            Some(Token::Close) => return Err(tokenizer.error("unexpected }")),
+            Some(Token::Open) => return Err(tokenizer.error("unexpected {")),
+            None if nested => return Err(tokenizer.error("missing }")),
+            None => return Ok(()),
+        };
+        match tokenizer.next()? {
+            Some(Token::Text(value)) => block.properties.push((key, value)),
+            Some(Token::Open) => {
+                let mut child = Block {
+                    name: key,
+                    ..Block::default()
+                };
+                parse_members(tokenizer, &mut child, true)?;
+                block.children.push(child);
+            }
+            _ => return Err(tokenizer.error("missing value")),
+        }
+    }
+}
+
+/// Parse a KeyValues file into a root block holding the top level blocks.
+pub fn parse(source: &str) -> Result<Block<'_>, KeyValuesError> {
+    let mut tokenizer = Tokenizer::new(source);
+    let mut root = Block::default();
+    parse_members(&mut tokenizer, &mut root, false)?;
+    Ok(root)
+}
+
+/// Entities the compiler removes from the entity lump, their data ends up elsewhere in the bsp.
+///
+/// `func_detail` brushes are merged into the world, `func_instance` is expanded into the
+/// entities of the instanced vmf, which isn't read.
+const COMPILED_OUT: &[&str] = &[
+    "env_cubemap",
+    "func_detail",
+    "func_instance",
+    "func_viscluster",
+    "info_no_dynamic_shadow",
+    "info_null",
+    "info_overlay",
+    "info_overlay_transition",
+    "prop_static",
+];
+
+/// Brush entities compiled into portals or occluders instead of a brush model.
+const NOT_BRUSH_MODELS: &[&str] = &["func_areaportal", "func_areaportalwindow", "func_occluder"];
+
+/// The entity lump a compile of the vmf would roughly contain.
+///
+/// The outputs in `connections` become keyvalues like they do when compiling,
+/// hidden entities are kept, the entities in [`COMPILED_OUT`] and editor-only blocks are left out,
+/// and the remaining brush entities get `*N` model references in the order the compiler assigns them.
+/// Instances aren't expanded.
+pub fn entity_lump(source: &str) -> Result<String, KeyValuesError> {
+    let root = parse(source)?;
+    let mut entities = Vec::new();
+    let mut brush_models = 0;
+    // hidden objects are still compiled
+    let blocks = root.children.iter().flat_map(|block| {
+        if block.name.eq_ignore_ascii_case("hidden") {
+            block.children.iter().collect()
+        } else {
+            vec![block]
+        }
+    });
+    for block in blocks {
+        let world = block.name.eq_ignore_ascii_case("world");
+        if !world && !block.name.eq_ignore_ascii_case("entity") {
+            continue;
+        }
+        let classname = block
+            .properties
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("classname"))
+            .map_or(String::new(), |(_, value)| value.to_ascii_lowercase());
+        if !world && COMPILED_OUT.contains(&classname.as_str()) {
+            continue;
+        }
+        let mut properties = Vec::new();
+        for &(key, value) in &block.properties {
+            // the compiler keeps the editor id as hammerid
+            let key = if key.eq_ignore_ascii_case("id") {
+                "hammerid"
+            } else {
+                key
+            };
+            properties.push((key.to_owned(), value.to_owned()));
+        }
+        let has_solids = block.children.iter().any(|child| {
+            child.name.eq_ignore_ascii_case("solid")
+                || child.name.eq_ignore_ascii_case("hidden")
+                    && child
+                        .children
+                        .iter()
+                        .any(|child| child.name.eq_ignore_ascii_case("solid"))
+        });
+        let has_model = properties
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case("model"));
+        if !world && has_solids && !has_model && !NOT_BRUSH_MODELS.contains(&classname.as_str()) {
+            brush_models += 1;
+            properties.push(("model".to_owned(), format!("*{brush_models}")));
+        }
+        for connections in block
+            .children
+            .iter()
+            .filter(|child| child.name.eq_ignore_ascii_case("connections"))
+        {
+            for &(output, target) in &connections.properties {
+                properties.push((output.to_owned(), target.to_owned()));
+            }
+        }
+
+        entities.push(properties);
+    }
+    Ok(crate::lump::format_entities(entities))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VMF: &str = r#"versioninfo
+{
+    "editorversion" "400"
+}
+world
+{
+    "id" "1"
+    "classname" "worldspawn"
+    solid { "id" "2" }
+}
+entity
+{
+    "id" "3"
+    "classname" "func_detail"
+    solid { "id" "4" }
+}
+entity
+{
+    "id" "5"
+    "classname" "func_door"
+    "targetname" "Door"
+    solid { "id" "6" }
+    connections
+    {
+        "OnOpen" "Relay,Trigger,,0,-1"
+        "OnOpen" "Light,TurnOn,,1,-1"
+    }
+}
+entity
+{
+    "id" "7"
+    "classname" "info_null"
+}
+entity
+{
+    "id" "8"
+    "classname" "func_areaportal"
+    solid { "id" "9" }
+}
+entity
+{
+    "id" "10"
+    "classname" "func_brush"
+    solid { "id" "11" }
+    editor { "color" "0 255 0" }
+}
+hidden
+{
+    entity
+    {
+        "id" "12"
+        "classname" "func_wall"
+        hidden
+        {
+            solid { "id" "13" }
+        }
+    }
+}
+entity
+{
+    "id" "14"
+    "classname" "func_button"
+    solid { "id" "15" }
+}
+"#;
+
+    #[test]
+    fn entity_lump_numbers_surviving_brush_entities() {
+        let lump = entity_lump(VMF).unwrap();
+        let entities = vbsp::Entities { entities: lump };
+        let classes: Vec<_> = entities
+            .iter()
+            .map(|entity| {
+                (
+                    entity.prop("classname").unwrap().to_owned(),
+                    entity.prop("model").map(str::to_owned),
+                )
+            })
+            .collect();
+        assert_eq!(
+            classes,
+            [
+                ("worldspawn".to_owned(), None),
+                ("func_door".to_owned(), Some("*1".to_owned())),
+                ("func_areaportal".to_owned(), None),
+                ("func_brush".to_owned(), Some("*2".to_owned())),
+                ("func_wall".to_owned(), Some("*3".to_owned())),
+                ("func_button".to_owned(), Some("*4".to_owned())),
+            ]
+        );
+        let door = entities.iter().nth(1).unwrap();
+        let outputs: Vec<_> = door
+            .properties()
+            .filter(|(key, _)| *key == "OnOpen")
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(outputs, ["Relay,Trigger,,0,-1", "Light,TurnOn,,1,-1"]);
+        assert_eq!(door.prop("hammerid"), Some("5"));
+    }
+
+    #[test]
+    fn parse_errors_have_lines() {
+        let error = parse("entity\n{\n\"classname\" \"x\"\n").unwrap_err();
+        assert_eq!(error.line, 4);
+        assert_eq!(error.message, "missing }");
+        assert!(parse("\"key\" \"unterminated").is_err());
+    }
+}